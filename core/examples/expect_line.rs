@@ -15,13 +15,10 @@ fn main() {
 
         println!("{:?}", String::from_utf8_lossy(m.as_bytes()));
 
-        let is_eof = m[0].is_empty();
-        if is_eof {
-            break;
-        }
-
-        if m[0] == [b'\n'] {
-            continue;
+        match m.matched_index() {
+            Some(2) => break,
+            Some(1) => continue,
+            _ => {}
         }
 
         println!("{:?}", String::from_utf8_lossy(&m[0]));
@@ -1,12 +1,12 @@
-use anticipate::{repl::spawn_python, Expect, Regex};
+use anticipate::{repl::spawn_python, Regex};
 
 fn main() {
     let mut p = spawn_python().unwrap();
 
     p.execute("import platform").unwrap();
-    p.send_line("platform.node()").unwrap();
-
-    let found = p.expect(Regex(r"'.*'")).unwrap();
+    let found = p
+        .send_line_and_expect("platform.node()", Regex(r"'.*'"))
+        .unwrap();
 
     println!(
         "Platform {}",
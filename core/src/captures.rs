@@ -1,4 +1,7 @@
-use std::ops::Index;
+use std::{
+    borrow::Cow,
+    ops::{Index, Range},
+};
 
 use crate::needle::Match;
 
@@ -29,6 +32,81 @@ impl Captures {
             .map(|m| &self.buf[m.start()..m.end()])
     }
 
+    /// match_range returns the byte offsets of a match by index.
+    ///
+    /// Offsets are relative to [Self::as_bytes], the bytes
+    /// before the match followed by the match itself, so
+    /// `&m.as_bytes()[range]` equals the matched bytes.
+    ///
+    /// The built-in needles such as [crate::Regex] and string
+    /// literals always report valid ranges; `None` is returned
+    /// when the index is out of bounds or a custom [crate::Needle]
+    /// reported a range outside of the consumed bytes.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 'Hello World'").unwrap();
+    /// let m = p.expect("World").unwrap();
+    /// assert_eq!(m.match_range(0), Some(6..11));
+    /// ```
+    pub fn match_range(&self, index: usize) -> Option<Range<usize>> {
+        self.matches
+            .get(index)
+            .map(Match::range)
+            .filter(|r| r.start <= r.end && r.end <= self.buf.len())
+    }
+
+    /// get_str returns a match by index as a string.
+    ///
+    /// Invalid UTF-8 sequences are replaced with
+    /// [U+FFFD REPLACEMENT CHARACTER](std::char::REPLACEMENT_CHARACTER).
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 'user@host'").unwrap();
+    /// let m = p.expect(anticipate::Regex("(\\w+)@(\\w+)")).unwrap();
+    /// assert_eq!(m.get_str(1).unwrap(), "user");
+    /// assert_eq!(m.get_str(2).unwrap(), "host");
+    /// ```
+    pub fn get_str(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.get(index).map(String::from_utf8_lossy)
+    }
+
+    /// matched_str returns the whole match as a string.
+    ///
+    /// An empty string is returned when nothing was matched.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 123").unwrap();
+    /// let m = p.expect(anticipate::Regex("\\d+")).unwrap();
+    /// assert_eq!(m.matched_str(), "123");
+    /// ```
+    pub fn matched_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.get(0).unwrap_or_default())
+    }
+
+    /// matched_index returns the index of the [crate::Any]
+    /// lookup that produced the match.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 'Continue?'").unwrap();
+    /// let m = p.expect(anticipate::Any(["Overwrite?", "Continue?"])).unwrap();
+    /// assert_eq!(m.matched_index(), Some(1));
+    /// ```
+    pub fn matched_index(&self) -> Option<usize> {
+        self.matches.first().and_then(Match::alternative)
+    }
+
     /// Matches returns a list of matches.
     pub fn matches(&self) -> MatchIter<'_> {
         MatchIter::new(self)
@@ -39,6 +117,20 @@ impl Captures {
         &self.buf[..self.left_most_index()]
     }
 
+    /// before_str returns the bytes before the match as a string.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 'Hello World'").unwrap();
+    /// let m = p.expect("World").unwrap();
+    /// assert_eq!(m.before_str(), "Hello ");
+    /// ```
+    pub fn before_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.before())
+    }
+
     /// as_bytes returns all bytes involved in a match, e.g. before the match and
     /// in a match itself.
     ///
@@ -112,6 +204,7 @@ impl ExactSizeIterator for MatchIter<'_> {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{Any, NBytes, Needle, Regex};
 
     #[test]
     fn test_captures_get() {
@@ -205,6 +298,72 @@ mod tests {
         assert_eq!(m.before(), b"".as_ref());
     }
 
+    #[test]
+    fn test_captures_matched_index() {
+        let buf = b"Overwrite? [y/n]";
+        let any = Any::boxed(vec![
+            Box::new("Continue?"),
+            Box::new(Regex("Overwrite\\?")),
+            Box::new(NBytes(4)),
+        ]);
+        let m = Captures::new(buf.to_vec(), any.check(buf, false).unwrap());
+        assert_eq!(m.matched_index(), Some(1));
+        assert_eq!(m.matched_str(), "Overwrite?");
+
+        let m =
+            Captures::new(buf.to_vec(), any.check(b"Yes", false).unwrap());
+        assert_eq!(m.matched_index(), None);
+
+        let m =
+            Captures::new(buf.to_vec(), "Over".check(buf, false).unwrap());
+        assert_eq!(m.matched_index(), None);
+    }
+
+    #[test]
+    fn test_captures_match_range() {
+        let buf = b"login: user@host";
+        let m = Captures::new(
+            buf.to_vec(),
+            Regex("(\\w+)@(\\w+)").check(buf, false).unwrap(),
+        );
+        assert_eq!(m.match_range(0), Some(7..16));
+        assert_eq!(m.match_range(1), Some(7..11));
+        assert_eq!(m.match_range(2), Some(12..16));
+        assert_eq!(m.match_range(3), None);
+        assert_eq!(&m.as_bytes()[m.match_range(2).unwrap()], b"host");
+
+        let m =
+            Captures::new(buf.to_vec(), "user".check(buf, false).unwrap());
+        assert_eq!(m.match_range(0), Some(7..11));
+        assert_eq!(m.before().len(), 7);
+
+        // Range reported by a custom needle outside the buffer
+        let m =
+            Captures::new(b"Hello World".to_vec(), vec![Match::new(0, 100)]);
+        assert_eq!(m.match_range(0), None);
+
+        let m = Captures::new(vec![], vec![]);
+        assert_eq!(m.match_range(0), None);
+    }
+
+    #[test]
+    fn test_captures_str() {
+        let m = Captures::new(
+            b"You can use \xF0iterator".to_vec(),
+            vec![Match::new(4, 7), Match::new(8, 11), Match::new(12, 21)],
+        );
+        assert_eq!(m.before_str(), "You ");
+        assert_eq!(m.matched_str(), "can");
+        assert_eq!(m.get_str(1).unwrap(), "use");
+        assert_eq!(m.get_str(2).unwrap(), "\u{FFFD}iterator");
+        assert_eq!(m.get_str(3), None);
+
+        let m = Captures::new(vec![], vec![]);
+        assert_eq!(m.before_str(), "");
+        assert_eq!(m.matched_str(), "");
+        assert_eq!(m.get_str(0), None);
+    }
+
     #[test]
     fn test_matches() {
         let m = Captures::new(
@@ -0,0 +1,66 @@
+//! Checksums to detect changes between runs.
+use std::hash::Hasher;
+
+const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a checksum.
+///
+/// Unlike the standard library hashers the value is stable
+/// across builds and platforms so it can be persisted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checksum(u64);
+
+impl Checksum {
+    /// Create a new checksum.
+    pub fn new() -> Self {
+        Self(OFFSET_BASIS)
+    }
+
+    /// Add bytes to the checksum.
+    pub fn update(&mut self, bytes: impl AsRef<[u8]>) {
+        for byte in bytes.as_ref() {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(PRIME);
+        }
+    }
+
+    /// Checksum value as a hex string.
+    pub fn to_hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for Checksum {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checksum;
+
+    #[test]
+    fn test_checksum_stable() {
+        assert_eq!("cbf29ce484222325", Checksum::new().to_hex());
+
+        let mut checksum = Checksum::new();
+        checksum.update("a");
+        assert_eq!("af63dc4c8601ec8c", checksum.to_hex());
+
+        let mut other = Checksum::new();
+        other.update("b");
+        assert_ne!(checksum, other);
+    }
+}
@@ -155,6 +155,59 @@ impl ControlCode {
     pub const SP: ControlCode = ControlCode::Space;
     /// See [ControlCode::Delete]
     pub const DEL: ControlCode = ControlCode::Delete;
+
+    /// List of all control codes with the mnemonic and the
+    /// character used in caret notation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anticipate::ControlCode;
+    ///
+    /// let (code, name, caret) = ControlCode::all()[3];
+    /// assert_eq!(code, ControlCode::EndOfText);
+    /// assert_eq!(name, "ETX");
+    /// assert_eq!(caret, 'C');
+    /// ```
+    pub fn all() -> &'static [(ControlCode, &'static str, char)] {
+        use ControlCode::*;
+        &[
+            (Null, "NUL", '@'),
+            (StartOfHeading, "SOH", 'A'),
+            (StartOfText, "STX", 'B'),
+            (EndOfText, "ETX", 'C'),
+            (EndOfTransmission, "EOT", 'D'),
+            (Enquiry, "ENQ", 'E'),
+            (Acknowledge, "ACK", 'F'),
+            (Bell, "BEL", 'G'),
+            (Backspace, "BS", 'H'),
+            (HorizontalTabulation, "HT", 'I'),
+            (LineFeed, "LF", 'J'),
+            (VerticalTabulation, "VT", 'K'),
+            (FormFeed, "FF", 'L'),
+            (CarriageReturn, "CR", 'M'),
+            (ShiftOut, "SO", 'N'),
+            (ShiftIn, "SI", 'O'),
+            (DataLinkEscape, "DLE", 'P'),
+            (DeviceControl1, "DC1", 'Q'),
+            (DeviceControl2, "DC2", 'R'),
+            (DeviceControl3, "DC3", 'S'),
+            (DeviceControl4, "DC4", 'T'),
+            (NegativeAcknowledge, "NAK", 'U'),
+            (SynchronousIdle, "SYN", 'V'),
+            (EndOfTransmissionBlock, "ETB", 'W'),
+            (Cancel, "CAN", 'X'),
+            (EndOfMedium, "EM", 'Y'),
+            (Substitute, "SUB", 'Z'),
+            (Escape, "ESC", '['),
+            (FileSeparator, "FS", '\\'),
+            (GroupSeparator, "GS", ']'),
+            (RecordSeparator, "RS", '^'),
+            (UnitSeparator, "US", '_'),
+            (Space, "SP", ' '),
+            (Delete, "DEL", '?'),
+        ]
+    }
 }
 
 impl From<ControlCode> for u8 {
@@ -372,3 +425,30 @@ impl AsRef<[u8]> for ControlCode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_covers_ascii_control_range() {
+        let bytes: Vec<u8> = ControlCode::all()
+            .iter()
+            .map(|(c, _, _)| (*c).into())
+            .collect();
+        let expected: Vec<u8> = (0..=32).chain([127]).collect();
+        assert_eq!(expected, bytes);
+    }
+
+    #[test]
+    fn test_all_caret_and_name() {
+        for (code, name, caret) in ControlCode::all() {
+            assert_eq!(Ok(*code), ControlCode::try_from(*caret));
+            assert_eq!(
+                Ok(*code),
+                ControlCode::try_from(format!("^{}", caret).as_str())
+            );
+            assert!(!name.is_empty());
+        }
+    }
+}
@@ -13,6 +13,17 @@ pub enum Error {
     /// An timeout was reached while waiting in expect call.
     #[error("reached the timeout of {0:?} expecting {1}")]
     ExpectTimeout(Duration, String),
+    /// Byte budget was exhausted before a match was found.
+    ///
+    /// Contains the budget and the accumulated bytes.
+    #[error("no match within a budget of {0} bytes")]
+    NoMatchWithinBudget(usize, Vec<u8>),
+    /// Unmatched output exceeded the maximum buffer size.
+    #[error("output buffer exceeded the maximum of {0} bytes")]
+    BufferOverflow(usize),
+    /// Pattern at an index of an ordered expect was not matched.
+    #[error("pattern {0} of the sequence was not matched: {1}")]
+    ExpectOrdered(usize, Box<Error>),
     /// Unhandled EOF error.
     #[error("unhandled EOF")]
     Eof,
@@ -24,3 +35,58 @@ pub enum Error {
     #[error(transparent)]
     Conpty(#[from] conpty::error::Error),
 }
+
+impl Error {
+    /// Kind of error as a stable identifier.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::CommandParsing => "command_parsing",
+            Self::RegexParsing => "regex_parsing",
+            Self::ExpectTimeout(_, _) => "expect_timeout",
+            Self::NoMatchWithinBudget(_, _) => "no_match_within_budget",
+            Self::BufferOverflow(_) => "buffer_overflow",
+            Self::ExpectOrdered(_, _) => "expect_ordered",
+            Self::Eof => "eof",
+            Self::Io(_) => "io",
+            #[cfg(windows)]
+            Self::Conpty(_) => "conpty",
+        }
+    }
+}
+
+/// Serialized as an object with the `kind` and `message`
+/// so errors from other libraries are flattened to strings.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("Error", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::Error;
+    use std::time::Duration;
+
+    #[test]
+    fn serialize_error() {
+        let error = Error::ExpectTimeout(Duration::from_secs(1), "$".into());
+        assert_eq!(
+            r#"{"kind":"expect_timeout","message":"reached the timeout of 1s expecting $"}"#,
+            serde_json::to_string(&error).unwrap()
+        );
+
+        let error = Error::Io(std::io::Error::other("broken pipe"));
+        assert_eq!(
+            r#"{"kind":"io","message":"broken pipe"}"#,
+            serde_json::to_string(&error).unwrap()
+        );
+    }
+}
@@ -9,15 +9,19 @@ mod control_code;
 mod error;
 mod needle;
 
+pub mod checksum;
 pub mod log;
 pub mod process;
 pub mod repl;
+pub mod report;
 pub(crate) mod session;
 
 pub use captures::Captures;
 pub use control_code::ControlCode;
 pub use error::Error;
-pub use needle::{Any, Eof, NBytes, Needle, Regex};
+pub use needle::{
+    Any, Eof, Match, NBytes, Needle, NormalizeNewlines, PromptOr, Regex,
+};
 
 #[cfg(unix)]
 pub use ptyprocess::{Signal, WaitStatus};
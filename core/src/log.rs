@@ -1,5 +1,8 @@
 //! Types for writing and formatting logs to stdout.
-use std::io::Write;
+use std::{
+    io::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 /// Trait for types that log read and writes to a child program.
 pub trait LogWriter {
@@ -9,6 +12,16 @@ pub trait LogWriter {
     fn log_write(&mut self, data: &[u8]);
 }
 
+impl<T: LogWriter + ?Sized> LogWriter for Box<T> {
+    fn log_read(&mut self, data: &[u8]) {
+        (**self).log_read(data);
+    }
+
+    fn log_write(&mut self, data: &[u8]) {
+        (**self).log_write(data);
+    }
+}
+
 /// Noop log writer does not log anything.
 pub struct NoopLogWriter;
 
@@ -26,8 +39,12 @@ impl LogWriter for NoopLogWriter {
 /// Be aware that if you are writing data that would be masked,
 /// for example, entering a password at an interactive prompt
 /// the plain text value will be logged.
-pub struct PrefixLogWriter {
-    writer: Box<dyn Write>,
+///
+/// The writer is boxed by default, [PrefixLogWriter::with_writer]
+/// accepts any writer so the log writer is `Send` when the
+/// writer is.
+pub struct PrefixLogWriter<W = Box<dyn Write>> {
+    writer: W,
 }
 
 impl Default for PrefixLogWriter {
@@ -43,6 +60,13 @@ impl PrefixLogWriter {
     pub fn new(writer: Box<dyn Write>) -> Self {
         Self { writer }
     }
+}
+
+impl<W: Write> PrefixLogWriter<W> {
+    /// Create a prefixed log writer that owns the writer.
+    pub fn with_writer(writer: W) -> Self {
+        Self { writer }
+    }
 
     fn log(&mut self, target: &str, data: &[u8]) {
         let _ = match std::str::from_utf8(data) {
@@ -54,7 +78,7 @@ impl PrefixLogWriter {
     }
 }
 
-impl LogWriter for PrefixLogWriter {
+impl<W: Write> LogWriter for PrefixLogWriter<W> {
     fn log_read(&mut self, data: &[u8]) {
         self.log("read", data);
     }
@@ -69,8 +93,12 @@ impl LogWriter for PrefixLogWriter {
 /// Be aware that if you are writing data that would be masked,
 /// for example, entering a password at an interactive prompt
 /// the plain text value will be logged.
-pub struct StandardLogWriter {
-    writer: Box<dyn Write>,
+///
+/// The writer is boxed by default, [StandardLogWriter::with_writer]
+/// accepts any writer so the log writer is `Send` when the
+/// writer is.
+pub struct StandardLogWriter<W = Box<dyn Write>> {
+    writer: W,
 }
 
 impl Default for StandardLogWriter {
@@ -88,7 +116,14 @@ impl StandardLogWriter {
     }
 }
 
-impl LogWriter for StandardLogWriter {
+impl<W: Write> StandardLogWriter<W> {
+    /// Create a standard log writer that owns the writer.
+    pub fn with_writer(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> LogWriter for StandardLogWriter<W> {
     fn log_read(&mut self, data: &[u8]) {
         let _ = self.writer.write_all(data);
     }
@@ -97,3 +132,174 @@ impl LogWriter for StandardLogWriter {
         let _ = self.writer.write_all(data);
     }
 }
+
+/// Timestamped log writer prefixes each line with the time.
+///
+/// Reads and writes are buffered separately until a newline
+/// so that fast output is logged as complete lines, partial
+/// lines are written when the log writer is dropped.
+///
+/// Be aware that if you are writing data that would be masked,
+/// for example, entering a password at an interactive prompt
+/// the plain text value will be logged.
+pub struct TimestampedLogWriter {
+    writer: Box<dyn Write + Send>,
+    read: Vec<u8>,
+    write: Vec<u8>,
+}
+
+impl Default for TimestampedLogWriter {
+    fn default() -> Self {
+        Self::new(Box::new(std::io::stdout()))
+    }
+}
+
+impl TimestampedLogWriter {
+    /// Create a new timestamped log writer.
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer,
+            read: Vec::new(),
+            write: Vec::new(),
+        }
+    }
+
+    fn log(&mut self, target: &str, data: &[u8]) {
+        let buffer = match target {
+            "read" => &mut self.read,
+            _ => &mut self.write,
+        };
+        buffer.extend_from_slice(data);
+        let Some(end) = buffer.iter().rposition(|b| *b == b'\n') else {
+            return;
+        };
+        let lines: Vec<u8> = buffer.drain(..=end).collect();
+        for line in lines.split_inclusive(|b| *b == b'\n') {
+            self.line(target, line);
+        }
+    }
+
+    fn line(&mut self, target: &str, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        let _ = writeln!(
+            &mut self.writer,
+            "{} {}: {:?}",
+            timestamp(SystemTime::now()),
+            target,
+            line
+        );
+    }
+}
+
+impl LogWriter for TimestampedLogWriter {
+    fn log_read(&mut self, data: &[u8]) {
+        self.log("read", data);
+    }
+
+    fn log_write(&mut self, data: &[u8]) {
+        self.log("write", data);
+    }
+}
+
+impl Drop for TimestampedLogWriter {
+    fn drop(&mut self) {
+        for target in ["read", "write"] {
+            let remainder = match target {
+                "read" => std::mem::take(&mut self.read),
+                _ => std::mem::take(&mut self.write),
+            };
+            if !remainder.is_empty() {
+                self.line(target, &remainder);
+            }
+        }
+        let _ = self.writer.flush();
+    }
+}
+
+/// Format a time as an ISO-8601 UTC timestamp with milliseconds.
+fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Convert days since the epoch to a civil date
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{timestamp, LogWriter, TimestampedLogWriter};
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        assert_eq!("2023-11-14T22:13:20.123Z", timestamp(time));
+        assert_eq!("1970-01-01T00:00:00.000Z", timestamp(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn test_timestamped_log_writer() {
+        let output = Shared::default();
+        let mut writer = TimestampedLogWriter::new(Box::new(output.clone()));
+        writer.log_read(b"hel");
+        writer.log_write(b"ls\n");
+        writer.log_read(b"lo\r\nwor");
+        writer.log_read(b"ld\r\npartial");
+        drop(writer);
+
+        let output =
+            String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(4, lines.len());
+        for (line, expected) in lines.iter().zip([
+            "write: \"ls\"",
+            "read: \"hello\"",
+            "read: \"world\"",
+            "read: \"partial\"",
+        ]) {
+            let (time, rest) = line.split_once(' ').unwrap();
+            assert_eq!(24, time.len());
+            assert!(time.ends_with('Z'));
+            assert_eq!(expected, rest);
+        }
+    }
+}
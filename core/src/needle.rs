@@ -4,6 +4,7 @@
 //! The list of provided implementations can be found in the documentation.
 
 use crate::error::Error;
+use std::ops::Range;
 
 /// Needle an interface for search of a match in a buffer.
 pub trait Needle: std::fmt::Debug {
@@ -16,12 +17,23 @@ pub trait Needle: std::fmt::Debug {
 pub struct Match {
     start: usize,
     end: usize,
+    alternative: Option<usize>,
 }
 
 impl Match {
     /// New construct's an intanse of a Match.
     pub fn new(start: usize, end: usize) -> Self {
-        Self { start, end }
+        Self {
+            start,
+            end,
+            alternative: None,
+        }
+    }
+
+    /// Set the index of the [Any] alternative that produced the match.
+    pub fn with_alternative(mut self, index: usize) -> Self {
+        self.alternative = Some(index);
+        self
     }
 
     /// Start returns a start index of a match.
@@ -33,6 +45,16 @@ impl Match {
     pub fn end(&self) -> usize {
         self.end
     }
+
+    /// Range returns the start and end index of a match.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Alternative returns the index of the [Any] lookup that matched.
+    pub fn alternative(&self) -> Option<usize> {
+        self.alternative
+    }
 }
 
 impl From<regex::bytes::Match<'_>> for Match {
@@ -153,6 +175,9 @@ impl Needle for char {
 ///
 /// It does checks lookups in order they were provided.
 ///
+/// The index of the lookup that matched is available
+/// from [Captures::matched_index](crate::Captures::matched_index).
+///
 /// # Example
 ///
 /// ```no_run,ignore
@@ -185,10 +210,13 @@ where
     T: Needle,
 {
     fn check(&self, buf: &[u8], eof: bool) -> Result<Vec<Match>, Error> {
-        for needle in self.0.iter() {
+        for (index, needle) in self.0.iter().enumerate() {
             let found = needle.check(buf, eof)?;
             if !found.is_empty() {
-                return Ok(found);
+                return Ok(found
+                    .into_iter()
+                    .map(|m| m.with_alternative(index))
+                    .collect());
             }
         }
 
@@ -223,6 +251,101 @@ where
     }
 }
 
+/// PromptOr matches a prompt or a continuation prompt.
+///
+/// The continuation prompt only matches at the end of the
+/// output so similar text printed by the program is ignored,
+/// use [crate::Captures::matched_index] to determine which
+/// prompt matched where `1` is the continuation prompt.
+///
+/// # Example
+///
+/// ```no_run
+/// use anticipate::{spawn, PromptOr};
+///
+/// let mut p = spawn("python3 -i").unwrap();
+/// p.send_line("def f():").unwrap();
+/// let m = p.expect(PromptOr(">>> ", "... ")).unwrap();
+/// assert_eq!(m.matched_index(), Some(1));
+/// ```
+#[derive(Debug)]
+pub struct PromptOr<P, C: AsRef<str> + std::fmt::Debug>(pub P, pub C);
+
+impl<P: Needle, C: AsRef<str> + std::fmt::Debug> Needle for PromptOr<P, C> {
+    fn check(&self, buf: &[u8], eof: bool) -> Result<Vec<Match>, Error> {
+        let found = self.0.check(buf, eof)?;
+        if !found.is_empty() {
+            return Ok(found
+                .into_iter()
+                .map(|m| m.with_alternative(0))
+                .collect());
+        }
+
+        let continuation = self.1.as_ref().as_bytes();
+        if !continuation.is_empty() && buf.ends_with(continuation) {
+            let start = buf.len() - continuation.len();
+            return Ok(
+                vec![Match::new(start, buf.len()).with_alternative(1)],
+            );
+        }
+        Ok(Vec::new())
+    }
+}
+
+/// NormalizeNewlines treats `\r\n` in the stream as `\n`.
+///
+/// Useful on Windows where the pseudo-terminal writes `\r\n`
+/// but the needle was written with bare `\n` line endings.
+///
+/// # Example
+///
+/// ```no_run
+/// use anticipate::{spawn, NormalizeNewlines};
+///
+/// let mut p = spawn("cat").unwrap();
+/// p.expect(NormalizeNewlines("one\ntwo")).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct NormalizeNewlines<N>(pub N);
+
+impl<N: Needle> Needle for NormalizeNewlines<N> {
+    fn check(&self, buf: &[u8], eof: bool) -> Result<Vec<Match>, Error> {
+        // Normalized bytes with start and end offsets
+        // into the original buffer for each byte
+        let mut normalized = Vec::with_capacity(buf.len());
+        let mut starts = Vec::with_capacity(buf.len() + 1);
+        let mut ends = Vec::with_capacity(buf.len() + 1);
+        let mut index = 0;
+        while index < buf.len() {
+            if buf[index] == b'\r' && buf.get(index + 1) == Some(&b'\n') {
+                normalized.push(b'\n');
+                starts.push(index);
+                ends.push(index + 2);
+                index += 2;
+            } else {
+                normalized.push(buf[index]);
+                starts.push(index);
+                ends.push(index + 1);
+                index += 1;
+            }
+        }
+        starts.push(buf.len());
+
+        let found = self.0.check(&normalized, eof)?;
+        Ok(found
+            .into_iter()
+            .map(|m| {
+                let end = if m.end() == 0 { 0 } else { ends[m.end() - 1] };
+                Match {
+                    start: starts[m.start()],
+                    end,
+                    alternative: m.alternative(),
+                }
+            })
+            .collect())
+    }
+}
+
 impl<T: Needle> Needle for &T {
     fn check(&self, buf: &[u8], eof: bool) -> Result<Vec<Match>, Error> {
         T::check(self, buf, eof)
@@ -358,6 +481,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_normalize_newlines() {
+        assert_eq!(
+            NormalizeNewlines("one\ntwo")
+                .check(b"> one\r\ntwo\r\n", false)
+                .unwrap(),
+            vec![Match::new(2, 10)]
+        );
+        assert_eq!(
+            NormalizeNewlines("\n").check(b"one\r\n", false).unwrap(),
+            vec![Match::new(3, 5)]
+        );
+        assert_eq!(
+            NormalizeNewlines("one\ntwo")
+                .check(b"one\ntwo", false)
+                .unwrap(),
+            vec![Match::new(0, 7)]
+        );
+        assert_eq!(
+            NormalizeNewlines("three").check(b"one\r\n", false).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_prompt_or() {
+        assert_eq!(
+            PromptOr("$ ", "> ").check(b"out\r\n$ ", false).unwrap(),
+            vec![Match::new(5, 7).with_alternative(0)]
+        );
+        assert_eq!(
+            PromptOr("$ ", "> ").check(b"echo 'a\r\n> ", false).unwrap(),
+            vec![Match::new(9, 11).with_alternative(1)]
+        );
+        assert_eq!(
+            PromptOr("$ ", "> ").check(b"a > b\r\n", false).unwrap(),
+            vec![]
+        );
+        assert_eq!(PromptOr("$ ", "").check(b"a", false).unwrap(), vec![]);
+    }
+
     #[test]
     fn test_any() {
         assert_eq!(
@@ -367,7 +531,7 @@ mod tests {
             ])
             .check(b"qwerty", false)
             .unwrap(),
-            vec![Match::new(1, 3)]
+            vec![Match::new(1, 3).with_alternative(0)]
         );
         assert_eq!(
             Any::boxed(vec![Box::new("123"), Box::new(NBytes(100))])
@@ -377,17 +541,17 @@ mod tests {
         );
         assert_eq!(
             Any(["123", "234", "rty"]).check(b"qwerty", false).unwrap(),
-            vec![Match::new(3, 6)]
+            vec![Match::new(3, 6).with_alternative(2)]
         );
         assert_eq!(
             Any(&["123", "234", "rty"][..])
                 .check(b"qwerty", false)
                 .unwrap(),
-            vec![Match::new(3, 6)]
+            vec![Match::new(3, 6).with_alternative(2)]
         );
         assert_eq!(
             Any(&["123", "234", "rty"]).check(b"qwerty", false).unwrap(),
-            vec![Match::new(3, 6)]
+            vec![Match::new(3, 6).with_alternative(2)]
         );
     }
 }
@@ -1,6 +1,6 @@
 //! Platform independent abstraction over an os process.
 
-use std::io::Result;
+use std::{io::Result, time::Duration};
 
 #[cfg(unix)]
 pub mod unix;
@@ -30,10 +30,18 @@ pub trait Healthcheck {
 }
 
 /// NonBlocking interface represens a [std::io::Read]er which can be turned in a non blocking mode
-/// so its read operations will return imideately.
+/// so its read operations will return immediately.
 pub trait NonBlocking {
     /// Sets a [std::io::Read]er into a non blocking mode.
     fn set_non_blocking(&mut self) -> Result<()>;
     /// Sets a [std::io::Read]er back into a blocking mode.
     fn set_blocking(&mut self) -> Result<()>;
+
+    /// Blocks until the [std::io::Read]er has data available or
+    /// the timeout elapses, a `None` timeout waits indefinitely.
+    ///
+    /// The default implementation returns immediately.
+    fn wait_readable(&mut self, _timeout: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
 }
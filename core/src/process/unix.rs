@@ -4,10 +4,12 @@ use super::{Healthcheck, NonBlocking, Process};
 use ptyprocess::{stream::Stream, PtyProcess};
 
 use std::{
+    fs::File,
     io::{self, Read, Result, Write},
     ops::{Deref, DerefMut},
     os::unix::prelude::{AsRawFd, RawFd},
     process::Command,
+    time::Duration,
 };
 
 /// A Unix representation of a [Process] via [PtyProcess]
@@ -67,6 +69,20 @@ impl DerefMut for UnixProcess {
     }
 }
 
+/// Program running on a terminal that was opened elsewhere.
+///
+/// The program is not a child of this process so it cannot
+/// be waited on or signalled, it is assumed to be alive for
+/// as long as the terminal is open.
+#[derive(Debug, Default)]
+pub struct AttachedProcess;
+
+impl Healthcheck for AttachedProcess {
+    fn is_alive(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+}
+
 /// A IO stream (write/read) of [UnixProcess].
 #[derive(Debug)]
 pub struct PtyStream {
@@ -77,6 +93,11 @@ impl PtyStream {
     fn new(stream: Stream) -> Self {
         Self { handle: stream }
     }
+
+    /// Create a stream for an open terminal file.
+    pub(crate) fn from_file(file: File) -> Self {
+        Self::new(Stream::new(file))
+    }
 }
 
 impl Write for PtyStream {
@@ -109,6 +130,18 @@ impl NonBlocking for PtyStream {
         let fd = self.handle.as_raw_fd();
         make_non_blocking(fd, false)
     }
+
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> Result<()> {
+        use nix::poll::{poll, PollFd, PollFlags};
+
+        let timeout = timeout
+            .map(|t| t.as_millis().min(i32::MAX as u128) as i32)
+            .unwrap_or(-1);
+        let mut fds =
+            [PollFd::new(self.handle.as_raw_fd(), PollFlags::POLLIN)];
+        let _ = poll(&mut fds, timeout).map_err(nix_error_to_io)?;
+        Ok(())
+    }
 }
 
 impl AsRawFd for PtyStream {
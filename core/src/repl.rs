@@ -3,7 +3,7 @@
 use crate::{
     error::Error,
     log::{LogWriter, NoopLogWriter},
-    spawn, Captures, Expect, Needle, Session,
+    spawn, Captures, Expect, Needle, OsProcess, Regex, Session,
 };
 use std::ops::{Deref, DerefMut};
 
@@ -49,6 +49,36 @@ pub fn spawn_bash() -> Result<ReplSession<NoopLogWriter>, Error> {
     Ok(bash)
 }
 
+/// Spawn a POSIX sh session.
+///
+/// It uses a custom prompt for deterministic control of the shell.
+#[cfg(unix)]
+pub fn spawn_sh() -> Result<ReplSession<NoopLogWriter>, Error> {
+    const DEFAULT_PROMPT: &str = "EXPECT_PROMPT";
+    let mut cmd = Command::new("sh");
+    let _ = cmd.env("PS1", DEFAULT_PROMPT);
+    // When sh is bash the prompt may be reset by startup files
+    // and bracketed paste mode must be disabled, other shells
+    // ignore PROMPT_COMMAND.
+    let _ = cmd.env(
+        "PROMPT_COMMAND",
+        "PS1=EXPECT_PROMPT; unset PROMPT_COMMAND; bind 'set enable-bracketed-paste off' 2>/dev/null",
+    );
+
+    let session = crate::DefaultSession::spawn(cmd)?;
+
+    let mut sh = ReplSession::new(
+        session,
+        DEFAULT_PROMPT.to_string(),
+        Some("exit".to_string()),
+        false,
+    );
+
+    sh.expect_prompt()?;
+
+    Ok(sh)
+}
+
 /// Spawn default python's IDLE.
 pub fn spawn_python() -> Result<ReplSession<NoopLogWriter>, Error> {
     // todo: check windows here
@@ -102,22 +132,24 @@ pub fn spawn_powershell() -> Result<ReplSession<NoopLogWriter>, Error> {
 /// you have a prompt where a user inputs commands and the shell
 /// which executes them and manages IO streams.
 #[derive(Debug)]
-pub struct ReplSession<O: LogWriter> {
+pub struct ReplSession<O: LogWriter, P = OsProcess> {
     /// The prompt, used for `wait_for_prompt`,
     /// e.g. ">>> " for python.
     prompt: String,
     /// A pseudo-teletype session with a spawned process.
-    session: Session<O>,
+    session: Session<O, P>,
     /// A command which will be called before termination.
     quit_command: Option<String>,
     /// Flag to see if a echo is turned on.
     is_echo_on: bool,
+    /// Flag to match the prompt as a regular expression.
+    is_prompt_regex: bool,
 }
 
-impl<O: LogWriter> ReplSession<O> {
+impl<O: LogWriter, P> ReplSession<O, P> {
     /// Creates a repl session.
     pub fn new(
-        session: Session<O>,
+        session: Session<O, P>,
         prompt: String,
         quit_command: Option<String>,
         is_echo: bool,
@@ -127,6 +159,7 @@ impl<O: LogWriter> ReplSession<O> {
             prompt,
             quit_command,
             is_echo_on: is_echo,
+            is_prompt_regex: false,
         }
     }
 
@@ -140,6 +173,16 @@ impl<O: LogWriter> ReplSession<O> {
         self.prompt = prompt
     }
 
+    /// Set whether the prompt is matched as a regular expression.
+    pub fn set_prompt_regex(&mut self, is_regex: bool) {
+        self.is_prompt_regex = is_regex
+    }
+
+    /// Determine if the prompt is matched as a regular expression.
+    pub fn is_prompt_regex(&self) -> bool {
+        self.is_prompt_regex
+    }
+
     /// Get a used quit command.
     pub fn get_quit_command(&self) -> Option<&str> {
         self.quit_command.as_deref()
@@ -151,19 +194,23 @@ impl<O: LogWriter> ReplSession<O> {
     }
 
     /// Get an inner session.
-    pub fn into_session(self) -> Session<O> {
+    pub fn into_session(self) -> Session<O, P> {
         self.session
     }
 }
 
-impl<O: LogWriter> ReplSession<O> {
+impl<O: LogWriter, P> ReplSession<O, P> {
     /// Block until prompt is found
     pub fn expect_prompt(&mut self) -> Result<Captures, Error> {
-        self.session.expect(&self.prompt)
+        if self.is_prompt_regex {
+            self.session.expect(Regex(&self.prompt))
+        } else {
+            self.session.expect(&self.prompt)
+        }
     }
 }
 
-impl<O: LogWriter> ReplSession<O> {
+impl<O: LogWriter, P> ReplSession<O, P> {
     /// Send a command to a repl and verifies that it exited.
     /// Returning it's output.
     pub fn execute<S: AsRef<str> + Clone>(
@@ -190,6 +237,31 @@ impl<O: LogWriter> ReplSession<O> {
         Ok(())
     }
 
+    /// Send a line to repl and expect a pattern in the response.
+    ///
+    /// When echo is on the pattern is matched after the input.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```ignore")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::repl::spawn_bash().unwrap();
+    /// let m = p.send_line_and_expect("echo $((6 * 7))", "42").unwrap();
+    /// assert_eq!(m.matched_str(), "42");
+    /// ```
+    pub fn send_line_and_expect<N>(
+        &mut self,
+        line: &str,
+        needle: N,
+    ) -> Result<Captures, Error>
+    where
+        N: Needle,
+    {
+        self.send_line(line)?;
+        self.session.flush()?;
+        self.session.expect(needle)
+    }
+
     /// Send a quit command.
     ///
     /// In async version we it won't be send on Drop so,
@@ -203,21 +275,21 @@ impl<O: LogWriter> ReplSession<O> {
     }
 }
 
-impl<O: LogWriter> Deref for ReplSession<O> {
-    type Target = Session<O>;
+impl<O: LogWriter, P> Deref for ReplSession<O, P> {
+    type Target = Session<O, P>;
 
     fn deref(&self) -> &Self::Target {
         &self.session
     }
 }
 
-impl<O: LogWriter> DerefMut for ReplSession<O> {
+impl<O: LogWriter, P> DerefMut for ReplSession<O, P> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.session
     }
 }
 
-impl<O: LogWriter> Expect for ReplSession<O> {
+impl<O: LogWriter, P> Expect for ReplSession<O, P> {
     fn send<B: AsRef<[u8]>>(&mut self, buf: B) -> std::io::Result<()> {
         self.session.send(buf)
     }
@@ -234,7 +306,7 @@ impl<O: LogWriter> Expect for ReplSession<O> {
     }
 }
 
-impl<O: LogWriter> Write for ReplSession<O> {
+impl<O: LogWriter, P> Write for ReplSession<O, P> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.session.write(buf)
     }
@@ -244,7 +316,7 @@ impl<O: LogWriter> Write for ReplSession<O> {
     }
 }
 
-impl<O: LogWriter> BufRead for ReplSession<O> {
+impl<O: LogWriter, P> BufRead for ReplSession<O, P> {
     fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
         self.session.fill_buf()
     }
@@ -254,7 +326,7 @@ impl<O: LogWriter> BufRead for ReplSession<O> {
     }
 }
 
-impl<O: LogWriter> Read for ReplSession<O> {
+impl<O: LogWriter, P> Read for ReplSession<O, P> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         self.session.read(buf)
     }
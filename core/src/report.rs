@@ -0,0 +1,151 @@
+//! Reports for the results of running a collection of files.
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+    time::{Duration, Instant},
+};
+
+/// Result of running a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    /// Name of the test case.
+    pub name: String,
+    /// Time taken to run the test case.
+    pub duration: Duration,
+    /// Error message when the test case failed.
+    pub failure: Option<String>,
+}
+
+impl TestCase {
+    /// Run a function and record the duration and outcome.
+    pub fn measure<T, E: fmt::Display>(
+        name: impl Into<String>,
+        func: impl FnOnce() -> Result<T, E>,
+    ) -> (Self, Result<T, E>) {
+        let started = Instant::now();
+        let result = func();
+        let case = Self {
+            name: name.into(),
+            duration: started.elapsed(),
+            failure: result.as_ref().err().map(|e| e.to_string()),
+        };
+        (case, result)
+    }
+
+    /// Determine if the test case failed.
+    pub fn is_failure(&self) -> bool {
+        self.failure.is_some()
+    }
+}
+
+/// Report accumulated across a run of multiple files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunReport {
+    /// Name of the test suite.
+    pub name: String,
+    /// Test case for each file.
+    pub cases: Vec<TestCase>,
+}
+
+impl RunReport {
+    /// Create an empty report.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            cases: Vec::new(),
+        }
+    }
+
+    /// Add a test case to the report.
+    pub fn push(&mut self, case: TestCase) {
+        self.cases.push(case);
+    }
+
+    /// Number of test cases that passed.
+    pub fn passed(&self) -> usize {
+        self.cases.len() - self.failed()
+    }
+
+    /// Number of test cases that failed.
+    pub fn failed(&self) -> usize {
+        self.cases.iter().filter(|c| c.is_failure()).count()
+    }
+
+    /// Total duration of all the test cases.
+    pub fn duration(&self) -> Duration {
+        self.cases.iter().map(|c| c.duration).sum()
+    }
+
+    /// Render the report as JUnit XML.
+    pub fn to_junit(&self) -> String {
+        let tests = self.cases.len();
+        let failures = self.failed();
+        let time = self.duration().as_secs_f64();
+        let name = escape(&self.name);
+
+        let mut xml = String::new();
+        // Writing to a string is infallible.
+        let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        let _ = writeln!(
+            xml,
+            r#"<testsuites name="{name}" tests="{tests}" failures="{failures}" time="{time:.3}">"#,
+        );
+        let _ = writeln!(
+            xml,
+            r#"  <testsuite name="{name}" tests="{tests}" failures="{failures}" errors="0" skipped="0" time="{time:.3}">"#,
+        );
+        for case in &self.cases {
+            let case_name = escape(&case.name);
+            let case_time = case.duration.as_secs_f64();
+            if let Some(failure) = &case.failure {
+                let message = escape(failure);
+                let _ = writeln!(
+                    xml,
+                    r#"    <testcase name="{case_name}" classname="{name}" time="{case_time:.3}">"#,
+                );
+                let _ = writeln!(
+                    xml,
+                    r#"      <failure message="{message}">{message}</failure>"#,
+                );
+                let _ = writeln!(xml, "    </testcase>");
+            } else {
+                let _ = writeln!(
+                    xml,
+                    r#"    <testcase name="{case_name}" classname="{name}" time="{case_time:.3}"/>"#,
+                );
+            }
+        }
+        let _ = writeln!(xml, "  </testsuite>");
+        let _ = writeln!(xml, "</testsuites>");
+        xml
+    }
+}
+
+/// Escape text for use in XML attributes and content.
+///
+/// Control characters are not allowed in XML 1.0 so they
+/// are replaced, program output may contain escape sequences.
+fn escape(value: &str) -> Cow<'_, str> {
+    let needs_escape = value.chars().any(|c| {
+        matches!(c, '&' | '<' | '>' | '"' | '\'')
+            || (c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+    });
+    if !needs_escape {
+        return Cow::Borrowed(value);
+    }
+
+    let mut s = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' => s.push_str("&gt;"),
+            '"' => s.push_str("&quot;"),
+            '\'' => s.push_str("&apos;"),
+            '\t' | '\n' | '\r' => s.push(c),
+            c if c.is_control() => s.push(char::REPLACEMENT_CHARACTER),
+            c => s.push(c),
+        }
+    }
+    Cow::Owned(s)
+}
@@ -0,0 +1,165 @@
+//! Hand control of a session to the user.
+use super::Session;
+use crate::{
+    control_code::ControlCode, error::Error, log::LogWriter,
+    process::NonBlocking,
+};
+use nix::{
+    errno::Errno,
+    poll::{poll, PollFd, PollFlags},
+    sys::termios::{self, SetArg, Termios},
+    unistd::isatty,
+};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::io::{AsFd, AsRawFd, RawFd},
+};
+
+impl<O, P, S> Session<O, P, S>
+where
+    O: LogWriter,
+    S: Read + Write + NonBlocking + AsRawFd,
+{
+    /// Interact with the child program until the escape code
+    /// is read from the input.
+    ///
+    /// Input is forwarded to the child program and output from
+    /// the child program is written to the output. When the
+    /// input is a terminal it is switched to raw mode until
+    /// this function returns.
+    ///
+    /// Input is read from a duplicate of the file descriptor
+    /// rather than through the reader so no input is held in a
+    /// buffer (such as the one used by [std::io::Stdin]) where
+    /// polling the descriptor would not see it.
+    ///
+    /// Returns when the escape code is read, the input is
+    /// closed or the child program exits.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use anticipate::ControlCode;
+    ///
+    /// let mut p = anticipate::spawn("bash").unwrap();
+    /// p.interact(
+    ///     &mut std::io::stdin(),
+    ///     &mut std::io::stdout(),
+    ///     ControlCode::GroupSeparator,
+    /// )
+    /// .unwrap();
+    /// ```
+    pub fn interact<I, W>(
+        &mut self,
+        input: &mut I,
+        output: &mut W,
+        escape: ControlCode,
+    ) -> Result<(), Error>
+    where
+        I: AsFd,
+        W: Write,
+    {
+        let escape: u8 = escape.into();
+        let mut reader = File::from(input.as_fd().try_clone_to_owned()?);
+        let _raw = RawMode::new(reader.as_raw_fd())?;
+        let mut buf = [0; 512];
+
+        // Output may already be buffered by previous expect calls
+        if self.forward_output(output, &mut buf)? {
+            return Ok(());
+        }
+
+        loop {
+            let mut fds = [
+                PollFd::new(reader.as_raw_fd(), PollFlags::POLLIN),
+                PollFd::new(self.get_stream().as_raw_fd(), PollFlags::POLLIN),
+            ];
+            match poll(&mut fds, -1) {
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(io::Error::from(e).into()),
+            }
+
+            let ready = |fd: &PollFd| {
+                fd.revents().map(|r| !r.is_empty()).unwrap_or(false)
+            };
+
+            if ready(&fds[1]) && self.forward_output(output, &mut buf)? {
+                return Ok(());
+            }
+
+            if ready(&fds[0]) {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    return Ok(());
+                }
+                let input = &buf[..n];
+                if let Some(index) = input.iter().position(|b| *b == escape) {
+                    self.send(&input[..index])?;
+                    return Ok(());
+                }
+                self.send(input)?;
+            }
+        }
+    }
+
+    /// Write available output from the child program.
+    ///
+    /// Returns `true` when the child program has exited.
+    fn forward_output<W: Write>(
+        &mut self,
+        output: &mut W,
+        buf: &mut [u8],
+    ) -> Result<bool, Error> {
+        loop {
+            match self.try_read(buf) {
+                Ok(0) => return Ok(true),
+                Ok(n) => {
+                    output.write_all(&buf[..n])?;
+                    output.flush()?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(false)
+                }
+                // Reading a pty after the child exits fails with EIO
+                Err(e) if e.raw_os_error() == Some(Errno::EIO as i32) => {
+                    return Ok(true)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Switch a terminal to raw mode until dropped.
+///
+/// Descriptors that are not a terminal are left unchanged.
+struct RawMode {
+    fd: RawFd,
+    original: Option<Termios>,
+}
+
+impl RawMode {
+    fn new(fd: RawFd) -> io::Result<Self> {
+        if !isatty(fd).unwrap_or(false) {
+            return Ok(Self { fd, original: None });
+        }
+        let original = termios::tcgetattr(fd)?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(fd, SetArg::TCSANOW, &raw)?;
+        Ok(Self {
+            fd,
+            original: Some(original),
+        })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(original) = &self.original {
+            let _ = termios::tcsetattr(self.fd, SetArg::TCSANOW, original);
+        }
+    }
+}
@@ -15,11 +15,16 @@
 //! p.read_line(&mut line).unwrap();
 //! ```
 
+#[cfg(unix)]
+mod interact;
 mod session;
 
 use crate::{log::*, process::Process, Error};
 use std::{process::Command, time::Duration};
 
+#[cfg(unix)]
+use std::{fs::File, os::fd::OwnedFd};
+
 #[cfg(unix)]
 type OsProc = crate::process::unix::UnixProcess;
 #[cfg(windows)]
@@ -35,7 +40,7 @@ pub type OsProcess = OsProc;
 /// OS process stream which is a default one for [`Session`].
 pub type OsProcessStream = OsProcStream;
 
-pub use session::Session;
+pub use session::{BufferPolicy, LineEnding, Session};
 
 /// Session without logging.
 pub type DefaultSession = Session<NoopLogWriter>;
@@ -63,6 +68,39 @@ impl<O: LogWriter> Session<O> {
     }
 }
 
+#[cfg(unix)]
+impl<O: LogWriter> Session<O, crate::process::unix::AttachedProcess> {
+    /// Attach a session to a terminal opened elsewhere.
+    ///
+    /// Use this to drive a program that was started by another
+    /// process, for example the primary side of a pseudo-terminal
+    /// or one end of a socket pair. Not supported on windows.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use anticipate::{log::NoopLogWriter, Session};
+    /// use std::{fs::File, os::fd::OwnedFd};
+    ///
+    /// let tty = File::options().read(true).write(true).open("/dev/ptmx");
+    /// let fd = OwnedFd::from(tty.unwrap());
+    /// let p = Session::attach(fd, Some(NoopLogWriter), None).unwrap();
+    /// ```
+    pub fn attach(
+        fd: OwnedFd,
+        logger: Option<O>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let stream = OsProcessStream::from_file(File::from(fd));
+        Ok(Self::new(
+            crate::process::unix::AttachedProcess,
+            stream,
+            logger,
+            timeout,
+        )?)
+    }
+}
+
 impl Session<NoopLogWriter> {
     /// Spawns a session on a platform process.
     ///
@@ -79,6 +117,33 @@ impl Session<NoopLogWriter> {
         let stream = process.open_stream()?;
         Ok(Self::new(process, stream, Some(NoopLogWriter), None)?)
     }
+
+    /// Spawns a session from a string command with environment
+    /// variables set for the program.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// use anticipate::DefaultSession;
+    ///
+    /// let env = [("GREETING".to_owned(), "hello".to_owned())];
+    /// let mut p =
+    ///     DefaultSession::spawn_with_env("sh -c 'echo $GREETING'", env)
+    ///         .unwrap();
+    /// p.expect("hello").unwrap();
+    /// ```
+    pub fn spawn_with_env<S: AsRef<str>>(
+        cmd: S,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, Error> {
+        let mut args = comma::parse_command(cmd.as_ref())
+            .filter(|args| !args.is_empty())
+            .ok_or(Error::CommandParsing)?;
+        let mut command = Command::new(args.remove(0));
+        let _ = command.args(args).envs(env);
+        Self::spawn(command)
+    }
 }
 
 impl Session<PrefixLogWriter> {
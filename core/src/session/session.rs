@@ -8,11 +8,56 @@ use std::{
 use crate::{
     error::Error,
     log::LogWriter,
-    needle::Needle,
+    needle::{Match, Needle},
     process::{Healthcheck, NonBlocking},
     Captures,
 };
 
+/// Policy when unmatched output exceeds the maximum buffer size.
+///
+/// See [Session::set_max_buffer].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BufferPolicy {
+    /// Drop the oldest bytes from the buffer.
+    #[default]
+    DropOldest,
+    /// Return [Error::BufferOverflow].
+    Error,
+}
+
+/// Line ending written by [Session::send_line].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Line feed (`\n`).
+    Lf,
+    /// Carriage return and line feed (`\r\n`).
+    CrLf,
+    /// Carriage return (`\r`).
+    Cr,
+}
+
+impl LineEnding {
+    /// Bytes for the line ending.
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::CrLf => b"\r\n",
+            Self::Cr => b"\r",
+        }
+    }
+}
+
+impl Default for LineEnding {
+    /// Line ending for the current platform.
+    fn default() -> Self {
+        if cfg!(windows) {
+            Self::CrLf
+        } else {
+            Self::Lf
+        }
+    }
+}
+
 /// Session represents a spawned process and it's streams.
 #[derive(Debug)]
 pub struct Session<
@@ -24,6 +69,10 @@ pub struct Session<
     stream: TryStream<O, S>,
     expect_timeout: Option<Duration>,
     expect_lazy: bool,
+    blocking_reads: bool,
+    max_buffer: Option<usize>,
+    buffer_policy: BufferPolicy,
+    line_ending: LineEnding,
 }
 
 impl<O, P, S> Session<O, P, S>
@@ -45,6 +94,10 @@ where
             stream,
             expect_timeout: Some(timeout),
             expect_lazy: false,
+            blocking_reads: false,
+            max_buffer: None,
+            buffer_policy: BufferPolicy::default(),
+            line_ending: LineEnding::default(),
         })
     }
 }
@@ -55,6 +108,11 @@ impl<O: LogWriter, P, S> Session<O, P, S> {
         self.expect_timeout = expect_timeout;
     }
 
+    /// Get the pty session's expect timeout.
+    pub fn expect_timeout(&self) -> Option<Duration> {
+        self.expect_timeout
+    }
+
     /// Set a expect algorithm to be either gready or lazy.
     ///
     /// Default algorithm is gready.
@@ -64,6 +122,56 @@ impl<O: LogWriter, P, S> Session<O, P, S> {
         self.expect_lazy = lazy;
     }
 
+    /// Set whether the gready expect algorithm blocks waiting
+    /// for output.
+    ///
+    /// When enabled and no match is pending the session waits
+    /// for the stream to become readable (bounded by the expect
+    /// timeout) instead of polling in a busy loop.
+    ///
+    /// Default is `false`.
+    pub fn set_blocking_reads(&mut self, blocking: bool) {
+        self.blocking_reads = blocking;
+    }
+
+    /// Set the maximum number of unmatched bytes buffered by
+    /// the gready expect algorithm.
+    ///
+    /// When the limit is exceeded and no match is pending the
+    /// buffer policy decides whether the oldest bytes are dropped
+    /// or an error is returned.
+    ///
+    /// Dropping keeps the newest `max_buffer` bytes so a partial
+    /// match at the end of the buffer is preserved as long as the
+    /// match is shorter than the limit. A match longer than the
+    /// limit, for example a regex spanning many lines, can lose
+    /// its start and is never found so the limit should be larger
+    /// than the longest expected match.
+    ///
+    /// Default is `None` which does not limit the buffer.
+    pub fn set_max_buffer(&mut self, max_buffer: Option<usize>) {
+        self.max_buffer = max_buffer;
+    }
+
+    /// Set the policy used when the maximum buffer size is exceeded.
+    ///
+    /// Default is [BufferPolicy::DropOldest].
+    pub fn set_buffer_policy(&mut self, policy: BufferPolicy) {
+        self.buffer_policy = policy;
+    }
+
+    /// Set the line ending written by [Session::send_line].
+    ///
+    /// Default is `\r\n` on windows and `\n` elsewhere.
+    pub fn set_line_ending(&mut self, ending: LineEnding) {
+        self.line_ending = ending;
+    }
+
+    /// Get the line ending written by [Session::send_line].
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
     /// Get a reference to original stream.
     pub fn get_stream(&self) -> &S {
         self.stream.as_ref()
@@ -141,10 +249,220 @@ impl<O: LogWriter, P, S: Read + NonBlocking> Session<O, P, S> {
         }
     }
 
+    /// Expect each pattern in order and return all the captures.
+    ///
+    /// Each pattern is matched against the output after the
+    /// previous match, on failure the error is wrapped in
+    /// [Error::ExpectOrdered] with the index of the pattern.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// use anticipate::{Needle, Regex};
+    ///
+    /// let mut p = anticipate::spawn("echo 'one two 3'").unwrap();
+    /// let needles: Vec<Box<dyn Needle>> =
+    ///     vec![Box::new("one"), Box::new("two"), Box::new(Regex("\\d"))];
+    /// let found = p.expect_ordered(needles).unwrap();
+    /// assert_eq!(found[2].matched_str(), "3");
+    /// ```
+    pub fn expect_ordered(
+        &mut self,
+        needles: Vec<Box<dyn Needle>>,
+    ) -> Result<Vec<Captures>, Error> {
+        needles
+            .iter()
+            .enumerate()
+            .map(|(index, needle)| {
+                self.expect(needle)
+                    .map_err(|e| Error::ExpectOrdered(index, Box::new(e)))
+            })
+            .collect()
+    }
+
+    /// Determine if the output ends with a continuation prompt.
+    ///
+    /// Available output is read without blocking and is not
+    /// consumed, useful after a timeout to detect a program
+    /// waiting for the rest of an unterminated input.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("cat").unwrap();
+    /// p.send("> ").unwrap();
+    /// std::thread::sleep(std::time::Duration::from_millis(200));
+    /// assert!(p.is_at_continuation("> ").unwrap());
+    /// ```
+    pub fn is_at_continuation(&mut self, cont: &str) -> Result<bool, Error> {
+        self.stream.read_available()?;
+        Ok(!cont.is_empty()
+            && self.stream.get_available().ends_with(cont.as_bytes()))
+    }
+
+    /// Expect a pattern using a timeout for this call only.
+    ///
+    /// The session's expect timeout is restored afterwards
+    /// including when an error is returned.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// use std::time::Duration;
+    ///
+    /// let mut p = anticipate::spawn("echo 123").unwrap();
+    /// let m = p
+    ///     .expect_with_timeout("123", Duration::from_millis(500))
+    ///     .unwrap();
+    /// assert_eq!(m.get(0).unwrap(), b"123");
+    /// ```
+    pub fn expect_with_timeout<N>(
+        &mut self,
+        needle: N,
+        timeout: Duration,
+    ) -> Result<Captures, Error>
+    where
+        N: Needle,
+    {
+        let previous = self.expect_timeout.replace(timeout);
+        let result = self.expect(needle);
+        self.expect_timeout = previous;
+        result
+    }
+
+    /// Expect a pattern returning `None` when the timeout is reached.
+    ///
+    /// Useful for polling loops, other errors such as EOF
+    /// are still returned.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// use std::time::Duration;
+    ///
+    /// let mut p = anticipate::spawn("cat").unwrap();
+    /// let found = p.try_expect("ready", Duration::from_millis(100));
+    /// assert!(found.unwrap().is_none());
+    /// ```
+    pub fn try_expect<N>(
+        &mut self,
+        needle: N,
+        timeout: Duration,
+    ) -> Result<Option<Captures>, Error>
+    where
+        N: Needle,
+    {
+        match self.expect_with_timeout(needle, timeout) {
+            Ok(found) => Ok(Some(found)),
+            Err(Error::ExpectTimeout(..)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Wait until no output has arrived for a duration.
+    ///
+    /// Useful for programs that do not print a known pattern
+    /// when they finish. The returned match contains all the
+    /// output read while waiting. Returns early on EOF.
+    ///
+    /// It returns an error if the expect timeout is reached
+    /// before the program is quiet.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// use std::time::Duration;
+    ///
+    /// let mut p = anticipate::spawn("echo 123").unwrap();
+    /// let m = p.expect_quiet(Duration::from_millis(200)).unwrap();
+    /// assert!(m.matched_str().contains("123"));
+    /// ```
+    pub fn expect_quiet(
+        &mut self,
+        idle: Duration,
+    ) -> Result<Captures, Error> {
+        self.expect_quiet_with(idle, || Ok::<_, Error>(()))
+    }
+
+    /// Wait until the program has not written any output for
+    /// the idle duration calling `check` while waiting.
+    ///
+    /// The check is called at least every 50 milliseconds so
+    /// callers can abort the wait, an error returned by the
+    /// check is returned immediately.
+    ///
+    /// See [Session::expect_quiet].
+    pub fn expect_quiet_with<E, F>(
+        &mut self,
+        idle: Duration,
+        mut check: F,
+    ) -> Result<Captures, E>
+    where
+        E: From<Error>,
+        F: FnMut() -> Result<(), E>,
+    {
+        let start = time::Instant::now();
+        let mut last_read = start;
+        let mut len = self.stream.get_available().len();
+        loop {
+            check()?;
+            let eof = self.stream.read_available().map_err(Error::from)?;
+            let available = self.stream.get_available().len();
+            if available > len {
+                len = available;
+                last_read = time::Instant::now();
+            }
+
+            if eof || last_read.elapsed() >= idle {
+                let data = self.stream.get_available().to_vec();
+                self.stream.consume_available(data.len());
+                let found = vec![Match::new(0, data.len())];
+                return Ok(Captures::new(data, found));
+            }
+
+            let mut remaining = idle.saturating_sub(last_read.elapsed());
+            if let Some(timeout) = self.expect_timeout {
+                if start.elapsed() > timeout {
+                    return Err(Error::ExpectTimeout(
+                        timeout,
+                        format!("quiet for {:?}", idle),
+                    )
+                    .into());
+                }
+                remaining =
+                    remaining.min(timeout.saturating_sub(start.elapsed()));
+            }
+            let remaining = remaining.min(Duration::from_millis(50));
+            self.stream
+                .wait_readable(Some(remaining))
+                .map_err(Error::from)?;
+        }
+    }
+
     /// Expect which fills as much as possible to the buffer.
     ///
     /// See [Session::expect].
     fn expect_gready<N>(&mut self, needle: N) -> Result<Captures, Error>
+    where
+        N: Needle,
+    {
+        self.expect_available(needle, None)
+    }
+
+    /// Expect reading all available data to the buffer.
+    ///
+    /// When `max_bytes` is set gives up once that many bytes
+    /// have accumulated without a match.
+    fn expect_available<N>(
+        &mut self,
+        needle: N,
+        max_bytes: Option<usize>,
+    ) -> Result<Captures, Error>
     where
         N: Needle,
     {
@@ -162,10 +480,23 @@ impl<O: LogWriter, P, S: Read + NonBlocking> Session<O, P, S> {
                 return Ok(Captures::new(involved_bytes, found));
             }
 
+            if let Some(max_bytes) =
+                max_bytes.filter(|max_bytes| data.len() >= *max_bytes)
+            {
+                let involved_bytes = data.to_vec();
+                self.stream.consume_available(involved_bytes.len());
+                return Err(Error::NoMatchWithinBudget(
+                    max_bytes,
+                    involved_bytes,
+                ));
+            }
+
             if eof {
                 return Err(Error::Eof);
             }
 
+            self.limit_buffer()?;
+
             if let Some(timeout) = self.expect_timeout {
                 if start.elapsed() > timeout {
                     return Err(Error::ExpectTimeout(
@@ -174,7 +505,34 @@ impl<O: LogWriter, P, S: Read + NonBlocking> Session<O, P, S> {
                     ));
                 }
             }
+
+            if self.blocking_reads {
+                let remaining = self
+                    .expect_timeout
+                    .map(|timeout| timeout.saturating_sub(start.elapsed()));
+                self.stream.wait_readable(remaining)?;
+            }
+        }
+    }
+
+    /// Apply the buffer policy when the buffer is too large.
+    fn limit_buffer(&mut self) -> Result<(), Error> {
+        if let Some(max_buffer) = self.max_buffer {
+            let len = self.stream.get_available().len();
+            if len > max_buffer {
+                match self.buffer_policy {
+                    BufferPolicy::DropOldest => {
+                        // Keep the newest bytes, they may hold the
+                        // start of a match
+                        self.stream.consume_available(len - max_buffer);
+                    }
+                    BufferPolicy::Error => {
+                        return Err(Error::BufferOverflow(max_buffer));
+                    }
+                }
+            }
         }
+        Ok(())
     }
 
     /// Expect which reads byte by byte.
@@ -240,6 +598,31 @@ impl<O: LogWriter, P, S: Read + NonBlocking> Session<O, P, S> {
         }
     }
 
+    /// Expect a pattern giving up once a number of bytes is read.
+    ///
+    /// Returns the match if found otherwise when `max_bytes` have
+    /// accumulated without a match [Error::NoMatchWithinBudget] is
+    /// returned with the consumed bytes.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 123").unwrap();
+    /// let m = p.expect_or_bytes("123", 1024).unwrap();
+    /// assert_eq!(m.get(0).unwrap(), b"123");
+    /// ```
+    pub fn expect_or_bytes<N>(
+        &mut self,
+        needle: N,
+        max_bytes: usize,
+    ) -> Result<Captures, Error>
+    where
+        N: Needle,
+    {
+        self.expect_available(needle, Some(max_bytes))
+    }
+
     /// Check verifies if a pattern is matched.
     /// Returns empty found structure if nothing found.
     ///
@@ -334,6 +717,95 @@ impl<O: LogWriter, P, S: Read + NonBlocking> Session<O, P, S> {
 
         Ok(false)
     }
+
+    /// Read the output that is currently available into the
+    /// internal buffer without blocking.
+    ///
+    /// The output is not consumed so later calls to expect can
+    /// still match it. Returns `true` when EOF was reached.
+    pub fn read_available(&mut self) -> io::Result<bool> {
+        self.stream.read_available()
+    }
+
+    /// Discard the output that is currently available.
+    ///
+    /// Reads without blocking and clears the internal buffer,
+    /// the discarded bytes are returned. Useful to remove
+    /// leftover output such as terminal escape sequences after
+    /// a command has finished.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 'Hello World'").unwrap();
+    /// p.expect("Hello").unwrap();
+    /// let rest = p.drain().unwrap();
+    /// ```
+    pub fn drain(&mut self) -> io::Result<Vec<u8>> {
+        self.stream.read_available()?;
+        let buf = self.stream.get_available().to_vec();
+        self.stream.consume_available(buf.len());
+        Ok(buf)
+    }
+}
+
+impl<O: LogWriter, P, S: Read + Write + NonBlocking> Session<O, P, S> {
+    /// Expect a pattern and nudge the program once on timeout.
+    ///
+    /// When the first attempt reaches the expect timeout the
+    /// `recovery` bytes (for example a newline) are sent and the
+    /// expect is retried, an error is returned if the second
+    /// attempt also fails.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("cat").unwrap();
+    /// p.set_expect_timeout(Some(std::time::Duration::from_millis(100)));
+    /// p.expect_with_recovery("\n", b"\n").unwrap();
+    /// ```
+    pub fn expect_with_recovery<N>(
+        &mut self,
+        needle: N,
+        recovery: &[u8],
+    ) -> Result<Captures, Error>
+    where
+        N: Needle,
+    {
+        match self.expect(&needle) {
+            Err(Error::ExpectTimeout(..)) => {
+                self.stream.write_all(recovery)?;
+                self.stream.flush()?;
+                self.expect(needle)
+            }
+            result => result,
+        }
+    }
+
+    /// Send a line and expect a pattern in the response.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("cat").unwrap();
+    /// let m = p.send_line_and_expect("Hello World", "World").unwrap();
+    /// assert_eq!(m.before_str(), "Hello ");
+    /// ```
+    pub fn send_line_and_expect<N>(
+        &mut self,
+        line: &str,
+        needle: N,
+    ) -> Result<Captures, Error>
+    where
+        N: Needle,
+    {
+        self.send_line(line)?;
+        self.stream.flush()?;
+        self.expect(needle)
+    }
 }
 
 impl<O: LogWriter, Proc, Stream: Write> Session<O, Proc, Stream> {
@@ -370,13 +842,8 @@ impl<O: LogWriter, Proc, Stream: Write> Session<O, Proc, Stream> {
     /// proc.send_line(ControlCode::try_from("^C").unwrap());
     /// ```
     pub fn send_line<B: AsRef<[u8]>>(&mut self, buf: B) -> io::Result<()> {
-        #[cfg(windows)]
-        const LINE_ENDING: &[u8] = b"\r\n";
-        #[cfg(not(windows))]
-        const LINE_ENDING: &[u8] = b"\n";
-
         self.stream.write_all(buf.as_ref())?;
-        self.write_all(LINE_ENDING)?;
+        self.write_all(self.line_ending.as_bytes())?;
 
         Ok(())
     }
@@ -395,6 +862,45 @@ impl<O: LogWriter, P, S: Read + NonBlocking> Session<O, P, S> {
     pub fn is_empty(&mut self) -> io::Result<bool> {
         self.stream.is_empty()
     }
+
+    /// Read all remaining output until EOF.
+    ///
+    /// Unlike [Read::read_to_end] bytes held in the internal
+    /// buffer by previous expect calls are included.
+    ///
+    /// Returns an error of kind [io::ErrorKind::TimedOut] if
+    /// the expect timeout is reached before EOF.
+    ///
+    /// # Example
+    ///
+    #[cfg_attr(windows, doc = "```no_run")]
+    #[cfg_attr(unix, doc = "```")]
+    /// let mut p = anticipate::spawn("echo 'Hello World'").unwrap();
+    /// p.expect("Hello").unwrap();
+    /// let rest = p.read_all_remaining().unwrap();
+    /// assert!(rest.starts_with(b" World"));
+    /// ```
+    pub fn read_all_remaining(&mut self) -> io::Result<Vec<u8>> {
+        let start = time::Instant::now();
+        while !self.stream.read_available()? {
+            let remaining = match self.expect_timeout {
+                Some(timeout) if start.elapsed() > timeout => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        Error::ExpectTimeout(timeout, "EOF".to_owned()),
+                    ));
+                }
+                Some(timeout) => {
+                    Some(timeout.saturating_sub(start.elapsed()))
+                }
+                None => None,
+            };
+            self.stream.wait_readable(remaining)?;
+        }
+        let buf = self.stream.get_available().to_vec();
+        self.stream.consume_available(buf.len());
+        Ok(buf)
+    }
 }
 
 impl<O: LogWriter, P, S: Write> Write for Session<O, P, S> {
@@ -486,6 +992,10 @@ impl<O: LogWriter, R: Read + NonBlocking> TryStream<O, R> {
         result
     }
 
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.get_mut().wait_readable(timeout)
+    }
+
     #[allow(clippy::wrong_self_convention)]
     fn is_empty(&mut self) -> io::Result<bool> {
         match self.try_read(&mut []) {
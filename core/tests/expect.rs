@@ -1,4 +1,7 @@
-use anticipate::{spawn, Eof, NBytes, Regex};
+use anticipate::{spawn, Eof, NBytes, Needle, Regex};
+
+#[cfg(windows)]
+use anticipate::NormalizeNewlines;
 use std::time::Duration;
 
 use std::io::Read;
@@ -41,6 +44,16 @@ fn expect_regex_lazy() {
     assert_eq!(m.get(0).unwrap(), b"lo");
 }
 
+#[cfg(unix)]
+#[test]
+fn expect_regex_group() {
+    let mut session = spawn("echo 'version 1.42'").unwrap();
+    let m = session.expect(Regex(r"version [0-9]+\.([0-9]+)")).unwrap();
+    assert_eq!(m.get(0).unwrap(), b"version 1.42");
+    assert_eq!(m.get(1).unwrap(), b"42");
+    assert_eq!(m.get_str(1).unwrap().parse::<u32>().unwrap(), 42);
+}
+
 #[cfg(windows)]
 #[test]
 fn expect_regex() {
@@ -148,3 +161,187 @@ fn expect_eof_timeout() {
         r => panic!("should raise TimeOut {:?}", r),
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn expect_with_recovery() {
+    use anticipate::DefaultSession;
+    use std::process::Command;
+
+    // Only responds once a line has been entered
+    let mut command = Command::new("sh");
+    command.args(["-c", "read line; echo ready"]);
+    let mut session = DefaultSession::spawn(command).unwrap();
+    session.set_expect_timeout(Some(Duration::from_millis(300)));
+    session.expect_with_recovery("ready", b"\n").unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_with_recovery_timeout() {
+    let mut p = spawn("sleep 3").expect("cannot run sleep 3");
+    p.set_expect_timeout(Some(Duration::from_millis(100)));
+    match p.expect_with_recovery("ready", b"\n") {
+        Err(anticipate::Error::ExpectTimeout(_, _)) => {}
+        r => panic!("should raise TimeOut {:?}", r),
+    }
+}
+
+#[cfg(windows)]
+#[test]
+fn expect_normalize_newlines() {
+    let mut session =
+        spawn(r#"pwsh -c "python ./tests/actions/cat/main.py""#).unwrap();
+    session.send_line("Hello").unwrap();
+    session.send_line("World").unwrap();
+    session.expect(NormalizeNewlines("Hello\nWorld")).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_or_bytes() {
+    let mut p = spawn("echo 'Hello World'").unwrap();
+    let m = p.expect_or_bytes("World", 1024).unwrap();
+    assert_eq!(m.get(0).unwrap(), b"World");
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_or_bytes_budget() {
+    let mut p = spawn("yes").expect("cannot run yes");
+    p.set_expect_timeout(Some(Duration::from_secs(5)));
+    match p.expect_or_bytes("never", 64) {
+        Err(anticipate::Error::NoMatchWithinBudget(64, bytes)) => {
+            assert!(bytes.len() >= 64);
+            assert!(bytes.starts_with(b"y\r\n"));
+        }
+        r => panic!("should exhaust byte budget {:?}", r),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_with_timeout() {
+    let mut p = spawn("cat").unwrap();
+    p.set_expect_timeout(Some(Duration::from_secs(5)));
+    match p.expect_with_timeout("never", Duration::from_millis(100)) {
+        Err(anticipate::Error::ExpectTimeout(timeout, _)) => {
+            assert_eq!(Duration::from_millis(100), timeout);
+        }
+        r => panic!("should timeout {:?}", r),
+    }
+    assert_eq!(Some(Duration::from_secs(5)), p.expect_timeout());
+
+    p.send_line("Hello World").unwrap();
+    p.expect_with_timeout("World", Duration::from_millis(500))
+        .unwrap();
+    assert_eq!(Some(Duration::from_secs(5)), p.expect_timeout());
+}
+
+#[cfg(unix)]
+#[test]
+fn try_expect() {
+    let mut p = spawn("cat").unwrap();
+    let found = p.try_expect("never", Duration::from_millis(100)).unwrap();
+    assert!(found.is_none());
+
+    p.send_line("Hello World").unwrap();
+    let found = p.try_expect("World", Duration::from_millis(500)).unwrap();
+    assert_eq!(found.unwrap().matched_str(), "World");
+
+    let mut p = spawn("echo done").unwrap();
+    p.try_expect("done", Duration::from_millis(500))
+        .unwrap()
+        .unwrap();
+    assert!(p.try_expect("never", Duration::from_secs(5)).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_ordered() {
+    let mut p = spawn("printf 'one\\ntwo\\nthree\\n'").unwrap();
+    let needles: Vec<Box<dyn Needle>> =
+        vec![Box::new("one"), Box::new("two"), Box::new(Regex("t.+e"))];
+    let found = p.expect_ordered(needles).unwrap();
+    assert_eq!(3, found.len());
+    assert_eq!(found[1].matched_str(), "two");
+    assert_eq!(found[2].matched_str(), "three");
+
+    let mut p = spawn("printf 'one\\ntwo\\nthree\\n'").unwrap();
+    let needles: Vec<Box<dyn Needle>> =
+        vec![Box::new("three"), Box::new("one")];
+    match p.expect_ordered(needles) {
+        Err(anticipate::Error::ExpectOrdered(index, _)) => {
+            assert_eq!(1, index);
+        }
+        r => panic!("should fail on the second pattern {:?}", r),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_max_buffer() {
+    use anticipate::{BufferPolicy, DefaultSession};
+    use std::process::Command;
+
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", "yes | head -n 200000; echo MARKER"]);
+    let mut p = DefaultSession::spawn(cmd).unwrap();
+    p.set_expect_timeout(Some(Duration::from_secs(10)));
+    p.set_max_buffer(Some(4096));
+    p.expect("MARKER").unwrap();
+
+    let mut p = spawn("yes").unwrap();
+    p.set_expect_timeout(Some(Duration::from_secs(10)));
+    p.set_max_buffer(Some(4096));
+    p.set_buffer_policy(BufferPolicy::Error);
+    match p.expect("never") {
+        Err(anticipate::Error::BufferOverflow(4096)) => {}
+        r => panic!("should overflow the buffer {:?}", r),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn expect_blocking_reads_idle() {
+    use anticipate::DefaultSession;
+    use nix::sys::resource::{getrusage, UsageWho};
+    use std::process::Command;
+
+    fn cpu_time() -> Duration {
+        let usage = getrusage(UsageWho::RUSAGE_THREAD).unwrap();
+        let user = usage.user_time();
+        let system = usage.system_time();
+        Duration::from_micros(
+            (user.tv_sec() * 1_000_000
+                + user.tv_usec()
+                + system.tv_sec() * 1_000_000
+                + system.tv_usec()) as u64,
+        )
+    }
+
+    let mut command = Command::new("sh");
+    command.args(["-c", "sleep 2; echo done"]);
+    let mut session = DefaultSession::spawn(command).unwrap();
+    session.set_expect_timeout(Some(Duration::from_secs(5)));
+    session.set_blocking_reads(true);
+
+    let before = cpu_time();
+    session.expect("done").unwrap();
+    let used = cpu_time() - before;
+
+    // A busy loop would consume close to the full two seconds
+    assert!(used < Duration::from_millis(500), "cpu time {:?}", used);
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_blocking_reads_timeout() {
+    let mut p = spawn("sleep 3").expect("cannot run sleep 3");
+    p.set_blocking_reads(true);
+    p.set_expect_timeout(Some(Duration::from_millis(100)));
+    match p.expect("done") {
+        Err(anticipate::Error::ExpectTimeout(_, _)) => {}
+        r => panic!("should raise TimeOut {:?}", r),
+    }
+}
@@ -415,6 +415,23 @@ fn try_read_to_end() {
     assert_eq!(&buf[..13], b"Hello World\r\n");
 }
 
+#[test]
+#[cfg(unix)]
+fn read_all_remaining() {
+    let mut cmd = Command::new("printf");
+    cmd.arg("first\nsecond\nthird\n");
+    let mut proc = DefaultSession::spawn(cmd).unwrap();
+    proc.expect("first").unwrap();
+
+    let buf = proc.read_all_remaining().unwrap();
+    assert_eq!(buf, b"\r\nsecond\r\nthird\r\n");
+
+    let mut proc = DefaultSession::spawn(Command::new("cat")).unwrap();
+    proc.set_expect_timeout(Some(Duration::from_millis(100)));
+    let err = proc.read_all_remaining().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}
+
 #[test]
 #[cfg(windows)]
 fn try_read_to_end() {
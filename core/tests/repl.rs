@@ -1,7 +1,7 @@
 #![cfg(unix)]
 
 use anticipate::{
-    repl::{spawn_bash, spawn_python},
+    repl::{spawn_bash, spawn_python, spawn_sh},
     ControlCode, Expect, WaitStatus,
 };
 use std::io::BufRead;
@@ -22,6 +22,20 @@ fn bash() {
     p.get_process_mut().exit(true).unwrap();
 }
 
+#[test]
+fn sh() {
+    let mut p = spawn_sh().unwrap();
+
+    p.send_line("echo Hello World").unwrap();
+    let mut msg = String::new();
+    p.read_line(&mut msg).unwrap();
+    assert!(msg.ends_with("Hello World\r\n"));
+
+    p.send(ControlCode::EOT).unwrap();
+
+    p.get_process_mut().exit(true).unwrap();
+}
+
 #[test]
 fn python() {
     let mut p = spawn_python().unwrap();
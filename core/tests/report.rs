@@ -0,0 +1,52 @@
+use std::{thread, time::Duration};
+
+use anticipate::report::{RunReport, TestCase};
+
+#[test]
+fn report_timing() {
+    let (case, result) = TestCase::measure("sleep.sh", || {
+        thread::sleep(Duration::from_millis(100));
+        Ok::<_, String>(())
+    });
+    assert!(result.is_ok());
+    assert!(!case.is_failure());
+    assert!(case.duration >= Duration::from_millis(100));
+    assert!(case.duration < Duration::from_secs(5));
+
+    let mut report = RunReport::new("anticipate");
+    report.push(case);
+    report.push(TestCase {
+        name: "fixed.sh".to_owned(),
+        duration: Duration::from_millis(1500),
+        failure: None,
+    });
+    assert_eq!(2, report.passed());
+    assert!(report.duration() >= Duration::from_millis(1600));
+
+    let xml = report.to_junit();
+    assert!(xml.contains(
+        r#"<testcase name="fixed.sh" classname="anticipate" time="1.500"/>"#
+    ));
+    assert!(xml.contains(r#"tests="2" failures="0""#));
+}
+
+#[test]
+fn report_escape_failure() {
+    let (case, result) = TestCase::measure("fail.sh", || {
+        Err::<(), _>("expected <a & b> got \"c\"\x1b[0m")
+    });
+    assert!(result.is_err());
+    assert!(case.is_failure());
+
+    let mut report = RunReport::new("anticipate");
+    report.push(case);
+    assert_eq!(1, report.failed());
+
+    let xml = report.to_junit();
+    let message = "expected &lt;a &amp; b&gt; got &quot;c&quot;\u{FFFD}[0m";
+    assert!(xml.contains(&format!(
+        r#"<failure message="{message}">{message}</failure>"#
+    )));
+    assert!(xml.contains(r#"tests="1" failures="1""#));
+    assert!(!xml.contains('\x1b'));
+}
@@ -79,6 +79,67 @@ fn send_line() {
     session.get_process_mut().exit(0).unwrap();
 }
 
+#[cfg(unix)]
+#[test]
+fn spawn_with_env() {
+    let env = [("ANTICIPATE_NAME".to_owned(), "Hello World".to_owned())];
+    let mut session =
+        DefaultSession::spawn_with_env("sh -c 'echo $ANTICIPATE_NAME'", env)
+            .unwrap();
+    session.expect("Hello World").unwrap();
+
+    assert!(matches!(
+        DefaultSession::spawn_with_env("", Vec::new()),
+        Err(anticipate::Error::CommandParsing)
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn expect_quiet() {
+    use std::time::{Duration, Instant};
+
+    let mut session =
+        spawn("sh -c 'echo one; sleep 0.3; echo two; sleep 5'").unwrap();
+    let start = Instant::now();
+    let m = session.expect_quiet(Duration::from_millis(1000)).unwrap();
+    let output = m.matched_str();
+    assert!(output.contains("one"));
+    assert!(output.contains("two"));
+    assert!(start.elapsed() < Duration::from_secs(4));
+}
+
+#[cfg(unix)]
+#[test]
+fn drain() {
+    let mut session = spawn("echo 'Hello World'").unwrap();
+    session.expect("Hello").unwrap();
+    // Wait for the rest of the output to arrive
+    std::thread::sleep(std::time::Duration::from_millis(300));
+
+    let drained = session.drain().unwrap();
+    assert!(String::from_utf8_lossy(&drained).contains("World"));
+    assert!(session.drain().unwrap().is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn interact() {
+    use anticipate::ControlCode;
+    use std::os::unix::net::UnixStream;
+
+    let mut session = spawn("cat").unwrap();
+    let (mut user, mut input) = UnixStream::pair().unwrap();
+    user.write_all(b"Hello World\n\x1dignored").unwrap();
+
+    let mut output = Vec::new();
+    session
+        .interact(&mut input, &mut output, ControlCode::GroupSeparator)
+        .unwrap();
+    session.expect("Hello World").unwrap();
+    assert!(session.is_alive().unwrap());
+}
+
 #[test]
 fn test_spawn_no_command() {
     #[cfg(unix)]
@@ -98,3 +159,110 @@ fn test_session_as_writer() {
         let _ = std::io::copy(&mut std::io::empty(), &mut session).unwrap();
     }
 }
+
+/// Stream that captures written bytes.
+#[derive(Default)]
+struct Capture(Vec<u8>);
+
+impl std::io::Read for Capture {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl std::io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn send_line_line_ending() {
+    use anticipate::{log::NoopLogWriter, LineEnding, Session};
+
+    let mut session =
+        Session::new((), Capture::default(), None::<NoopLogWriter>, None)
+            .unwrap();
+    assert_eq!(LineEnding::default(), session.line_ending());
+
+    for (ending, expected) in [
+        (LineEnding::Lf, &b"ls\n"[..]),
+        (LineEnding::CrLf, &b"ls\r\n"[..]),
+        (LineEnding::Cr, &b"ls\r"[..]),
+    ] {
+        session.get_stream_mut().0.clear();
+        session.set_line_ending(ending);
+        session.send_line("ls").unwrap();
+        assert_eq!(expected, session.get_stream().0.as_slice());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn attach() {
+    use anticipate::{log::NoopLogWriter, Session};
+    use std::{os::fd::OwnedFd, os::unix::net::UnixStream};
+
+    let (local, mut remote) = UnixStream::pair().unwrap();
+    let mut session =
+        Session::attach(OwnedFd::from(local), Some(NoopLogWriter), None)
+            .unwrap();
+    assert!(session.is_alive().unwrap());
+
+    session.send_line("ping").unwrap();
+    let mut buf = [0; 5];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping\n");
+
+    remote.write_all(b"pong\n").unwrap();
+    session.expect("pong").unwrap();
+}
+
+/// Stream that returns chunks of output, `None` signals that
+/// no data is available yet.
+struct Chunks(std::collections::VecDeque<Option<&'static [u8]>>);
+
+impl std::io::Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.pop_front() {
+            Some(Some(chunk)) => {
+                buf[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+            Some(None) => Err(std::io::ErrorKind::WouldBlock.into()),
+            None => Ok(0),
+        }
+    }
+}
+
+impl anticipate::process::NonBlocking for Chunks {
+    fn set_non_blocking(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_blocking(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn expect_max_buffer_boundary() {
+    use anticipate::{log::NoopLogWriter, Session};
+
+    // The marker starts before the limit is applied and
+    // finishes in the next read
+    let chunks = Chunks(
+        [Some(&b"xxxxxxxxxxxxMAR"[..]), None, Some(&b"KER"[..]), None].into(),
+    );
+    let mut session =
+        Session::new((), chunks, None::<NoopLogWriter>, None).unwrap();
+    session.set_max_buffer(Some(8));
+    let found = session.expect("MARKER").unwrap();
+    assert_eq!(b"MARKER", found.get(0).unwrap());
+    assert_eq!(b"xxxxx", found.before());
+}
@@ -0,0 +1,239 @@
+//! Builder for interpreter options.
+use crate::{
+    CinemaOptions, CommentStyle, InstructionHook, InterpreterOptions,
+    LogWriter, PromptPattern, Transcript,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
+use tracing::Level;
+
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
+
+/// Output file argument for the recording command.
+pub(crate) fn output_argument(output: &Path) -> String {
+    format!("{:#?}", output.to_string_lossy())
+}
+
+/// Builder for [InterpreterOptions].
+///
+/// Options that are not set use the values from
+/// [InterpreterOptions::default].
+#[derive(Default)]
+pub struct InterpreterOptionsBuilder {
+    options: InterpreterOptions,
+}
+
+impl InterpreterOptionsBuilder {
+    /// Command to execute in the pseudo-terminal.
+    pub fn command(mut self, command: impl Into<String>) -> Self {
+        self.options.command = command.into();
+        self
+    }
+
+    /// Record to a cast file using asciinema.
+    ///
+    /// Sets the command to run asciinema with the size from
+    /// the cinema options.
+    pub fn recording(
+        mut self,
+        output: impl AsRef<Path>,
+        overwrite: bool,
+        cinema: CinemaOptions,
+    ) -> Self {
+        let mut command =
+            format!("asciinema rec {}", output_argument(output.as_ref()));
+        if overwrite {
+            command.push_str(" --overwrite");
+        }
+        let (cols, rows) = cinema.size();
+        command.push_str(&format!(" --rows={}", rows));
+        command.push_str(&format!(" --cols={}", cols));
+        self.options.command = command;
+        self.options.cinema = Some(cinema);
+        self.options.output = Some(output.as_ref().to_owned());
+        self
+    }
+
+    /// Timeout in milliseconds.
+    pub fn timeout(mut self, timeout: u64) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Disable the timeout.
+    pub fn no_timeout(mut self) -> Self {
+        self.options.timeout = None;
+        self
+    }
+
+    /// Multiplier for timeouts in slow environments.
+    pub fn timeout_multiplier(mut self, multiplier: f64) -> Self {
+        self.options.timeout_multiplier = multiplier;
+        self
+    }
+
+    /// Maximum duration for the entire script.
+    pub fn total_timeout(mut self, limit: Duration) -> Self {
+        self.options.total_timeout = Some(limit);
+        self
+    }
+
+    /// Timeout waiting for the program to exit.
+    pub fn eof_timeout(mut self, limit: Duration) -> Self {
+        self.options.eof_timeout = Some(limit);
+        self
+    }
+
+    /// Identifier.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.options.id = Some(id.into());
+        self
+    }
+
+    /// Prompt.
+    pub fn prompt(mut self, prompt: impl Into<PromptPattern>) -> Self {
+        self.options.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Additional prompts matched by the waitany instruction.
+    pub fn prompts(mut self, prompts: Vec<String>) -> Self {
+        self.options.prompts = prompts;
+        self
+    }
+
+    /// Echo to stdout.
+    pub fn echo(mut self, echo: bool) -> Self {
+        self.options.echo = echo;
+        self
+    }
+
+    /// Format IO logged to stdout.
+    pub fn format(mut self, format: bool) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// Write logged IO to a file instead of stdout.
+    pub fn log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.log_file = Some(path.into());
+        self
+    }
+
+    /// Print comments.
+    pub fn print_comments(mut self, print_comments: bool) -> Self {
+        self.options.print_comments = print_comments;
+        self
+    }
+
+    /// Style for printed comments.
+    pub fn comment_style(mut self, style: CommentStyle) -> Self {
+        self.options.comment_style = style;
+        self
+    }
+
+    /// Match bare newlines in expect patterns against CRLF.
+    pub fn normalize_newlines(mut self, normalize: bool) -> Self {
+        self.options.normalize_newlines = normalize;
+        self
+    }
+
+    /// Custom log writer.
+    pub fn log_writer(
+        mut self,
+        log_writer: impl LogWriter + Send + 'static,
+    ) -> Self {
+        self.options.log_writer = Some(Box::new(log_writer));
+        self
+    }
+
+    /// Hook called before each instruction executes.
+    pub fn on_instruction(mut self, hook: InstructionHook) -> Self {
+        self.options.on_instruction = Some(hook);
+        self
+    }
+
+    /// Capture program output.
+    pub fn transcript(mut self, transcript: Transcript) -> Self {
+        self.options.transcript = Some(transcript);
+        self
+    }
+
+    /// Flag to cancel execution.
+    pub fn cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    /// Send end of transmission when the script finishes.
+    pub fn send_eof(mut self, send_eof: bool) -> Self {
+        self.options.send_eof = send_eof;
+        self
+    }
+
+    /// Shell commands to run before the script.
+    pub fn pre_commands(mut self, commands: Vec<String>) -> Self {
+        self.options.pre_commands = commands;
+        self
+    }
+
+    /// Shell commands to run after the script.
+    pub fn post_commands(mut self, commands: Vec<String>) -> Self {
+        self.options.post_commands = commands;
+        self
+    }
+
+    /// Working directory for the spawned program.
+    pub fn working_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Columns and rows for the pseudo-terminal.
+    pub fn size(mut self, cols: u16, rows: u16) -> Self {
+        self.options.size = Some((cols, rows));
+        self
+    }
+
+    /// Maximum level for log events while the script runs.
+    pub fn log_level(mut self, level: Level) -> Self {
+        self.options.log_level = Some(level);
+        self
+    }
+
+    /// Stop after an instruction whose name or argument
+    /// contains the text.
+    pub fn stop_after(mut self, text: impl Into<String>) -> Self {
+        self.options.stop_after = Some(text.into());
+        self
+    }
+
+    /// Continuation prompt shown by the program for incomplete input.
+    pub fn continuation_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.options.continuation_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Run the script against the terminal of a program that
+    /// was started elsewhere instead of spawning the command.
+    #[cfg(unix)]
+    pub fn attach_fd(mut self, fd: OwnedFd) -> Self {
+        self.options.attach_fd = Some(fd);
+        self
+    }
+
+    /// Maximum length in bytes of a line sent to the program.
+    pub fn max_line_length(mut self, length: usize) -> Self {
+        self.options.max_line_length = Some(length);
+        self
+    }
+
+    /// Build the options.
+    pub fn build(self) -> InterpreterOptions {
+        self.options
+    }
+}
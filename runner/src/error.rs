@@ -1,6 +1,55 @@
-use std::path::PathBuf;
+use std::{fmt, ops::Range, path::PathBuf};
 use thiserror::Error;
 
+/// Convert a byte offset into a 1-based line and column.
+pub(crate) fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[start..].chars().count() + 1;
+    (line, column)
+}
+
+/// Location of an error in a source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Byte range in the source.
+    pub span: Range<usize>,
+    /// Line number (1-based).
+    pub line: usize,
+    /// Column number (1-based).
+    pub column: usize,
+}
+
+impl Location {
+    /// Create a location for a span in the source.
+    pub fn new(source: &str, span: Range<usize>) -> Self {
+        let (line, column) = line_column(source, span.start);
+        Self { span, line, column }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+fn display_quit(quit: &Option<String>) -> String {
+    quit.as_ref()
+        .map(|quit| format!(" after the quit command '{}'", quit))
+        .unwrap_or_default()
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Error lexing a source file.
 #[derive(Debug, Error, PartialEq, Clone, Default)]
 #[doc(hidden)]
@@ -9,6 +58,9 @@ pub enum LexError {
     #[default]
     #[error("parser lex error")]
     Other,
+    /// Text that does not match a token.
+    #[error("unexpected token at byte {0}: '{1}'")]
+    UnexpectedToken(usize, String),
 }
 
 /// Errors generated by the library.
@@ -18,22 +70,183 @@ pub enum Error {
     #[error("Bad command arguments: '{0}'")]
     BadArguments(String),
 
-    /// Include file not found.
-    #[error("include file '{0}' not found ({1})")]
-    Include(String, PathBuf),
+    /// Script or include file could not be read.
+    #[error("failed to read '{}': {0}", .1.to_string_lossy())]
+    Read(#[source] std::io::Error, PathBuf),
+
+    /// Include file not found, lists the paths that were tried.
+    #[error(
+        "include file '{0}' not found (tried {}) at {2}",
+        display_paths(.1)
+    )]
+    Include(String, Vec<PathBuf>, Location),
+
+    /// Include pattern is not valid.
+    #[error("invalid include pattern: {0}")]
+    Pattern(#[from] glob::PatternError),
 
     /// Unknown instruction.
-    #[error("unknown instruction '{0}'")]
-    UnknownInstruction(String),
+    #[error("unknown instruction '{0}' at {1}")]
+    UnknownInstruction(String, Location),
+
+    /// Invalid expect captures instruction.
+    #[error(
+        "invalid expect-captures '{0}' at {1}, expected a pattern and names"
+    )]
+    InvalidCaptures(String, Location),
+
+    /// Invalid capture instruction.
+    #[error("invalid capture '{0}' at {1}, expected a name and pattern")]
+    InvalidCapture(String, Location),
+
+    /// Invalid expect sequence instruction.
+    #[error("invalid expectseq '{0}' at {1}, expected one or more patterns")]
+    InvalidSequence(String, Location),
+
+    /// Capture group was not matched.
+    #[error("capture group {1} for '{0}' was not matched")]
+    CaptureGroup(String, usize),
+
+    /// Invalid assert instruction.
+    #[error(
+        "invalid assert '{0}' at {1}, expected '$NAME == value' or '$NAME =~ regex'"
+    )]
+    InvalidAssertion(String, Location),
+
+    /// Assertion did not match.
+    #[error("assertion failed, expected '{expected}' but got '{actual}'")]
+    AssertionFailed {
+        /// Expected value or pattern.
+        expected: String,
+        /// Actual value of the variable.
+        actual: String,
+    },
+
+    /// Program exited with an unexpected status.
+    #[error(
+        "program exited with status {actual}{} but expected {expected}",
+        display_quit(.quit)
+    )]
+    ExitStatus {
+        /// Expected exit status.
+        expected: i32,
+        /// Actual exit status.
+        actual: i32,
+        /// Quit command sent before the program exited.
+        quit: Option<String>,
+    },
+
+    /// Invalid branch arm.
+    #[error("invalid branch arm '{0}' at {1}, expected 'PATTERN => LINE'")]
+    InvalidBranch(String, Location),
+
+    /// Branch is missing the end declaration.
+    #[error("branch at {0} is missing '#$ end'")]
+    UnterminatedBranch(Location),
+
+    /// Invalid hex string for sendbytes.
+    #[error("invalid hex '{0}' at {1}, expected pairs of hex digits")]
+    InvalidHex(String, Location),
+
+    /// Invalid require instruction.
+    #[error(
+        "invalid require '{0}' at {1}, expected 'PROGRAM' or 'PROGRAM >= VERSION'"
+    )]
+    InvalidRequirement(String, Location),
+
+    /// Program version is older than required.
+    #[error("program '{program}' version {found} is older than {required}")]
+    VersionMismatch {
+        /// Program that was checked.
+        program: String,
+        /// Minimum version.
+        required: String,
+        /// Version reported by the program.
+        found: String,
+    },
+
+    /// Invalid line ending.
+    #[error("invalid line ending '{0}' at {1}, expected lf, crlf or cr")]
+    InvalidLineEnding(String, Location),
 
     /// Invalid control code.
     #[error("invalid control code '{0}'")]
     InvalidControlCode(String),
 
+    /// Unknown signal name.
+    #[error("invalid signal '{0}'")]
+    InvalidSignal(String),
+
+    /// Transcript does not match the golden file.
+    #[error("transcript does not match golden file {0}:\n{1}")]
+    GoldenMismatch(PathBuf, String),
+
+    /// Program is waiting at a continuation prompt.
+    #[error(
+        "program is waiting at the continuation prompt '{0}', check for unbalanced quotes or brackets"
+    )]
+    UnexpectedContinuation(String),
+
+    /// Script exceeded the total timeout.
+    #[error("script exceeded the maximum duration of {0:?}")]
+    TotalTimeout(std::time::Duration),
+
+    /// Recording options are missing an output file.
+    #[error("recording requires an output file")]
+    RecordingOutput,
+
+    /// Program used to record is not installed.
+    #[error("program '{0}' not found in PATH, is it installed?")]
+    MissingTool(String),
+
+    /// Program used to render a cast is not installed.
+    #[error("render program '{0}' not found, is it installed?")]
+    RenderToolMissing(String),
+
+    /// Program used to render a cast failed.
+    #[error("render program '{0}' failed ({1})")]
+    RenderFailed(String, std::process::ExitStatus),
+
+    /// Execution was cancelled.
+    #[error("execution cancelled")]
+    Cancelled,
+
     /// Script pragma must be first instruction.
     #[error("pragma declaration ($!) must be the first instruction")]
     PragmaFirst,
 
+    /// Setup commands must be at the top of the script.
+    #[error(
+        "exec at {0} must come before other instructions except the pragma"
+    )]
+    ExecFirst(Location),
+
+    /// Setup command exited with a failure status.
+    #[error("exec '{0}' failed ({1})")]
+    ExecFailed(String, std::process::ExitStatus),
+
+    /// Setup command did not exit before the timeout.
+    #[error("exec '{0}' exceeded the timeout of {1:?}")]
+    ExecTimeout(String, std::time::Duration),
+
+    /// Eval command after visible output in a recording.
+    #[error(
+        "eval '{0}' must come before visible instructions when recording"
+    )]
+    EvalVisible(String),
+
+    /// Operation needs a spawned program but the session is attached.
+    #[error("{0} is not supported for an attached terminal")]
+    Attached(&'static str),
+
+    /// Instruction requires a feature that is not enabled.
+    #[error("{0} requires the '{0}' feature")]
+    Unsupported(&'static str),
+
+    /// Line sent to the program exceeds the maximum length.
+    #[error("line of {0} bytes exceeds the maximum length of {1} bytes")]
+    LineTooLong(usize, usize),
+
     /// Error generated by the io module.
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -0,0 +1,80 @@
+//! Filter log events by level for a single script.
+use tracing::{
+    dispatcher,
+    level_filters::LevelFilter,
+    span,
+    subscriber::{Interest, Subscriber},
+    Dispatch, Event, Level, Metadata,
+};
+
+/// Subscriber that forwards events at or below a level to the
+/// current dispatcher.
+///
+/// The wrapped subscriber still applies its own filter so
+/// the level can only reduce the events that are logged.
+pub(crate) struct LevelFilterSubscriber {
+    inner: Dispatch,
+    level: Level,
+}
+
+impl LevelFilterSubscriber {
+    /// Run a function with events filtered to a level.
+    pub fn scope<T>(level: Level, func: impl FnOnce() -> T) -> T {
+        let inner = dispatcher::get_default(|current| current.clone());
+        let dispatch = Dispatch::new(Self { inner, level });
+        dispatcher::with_default(&dispatch, func)
+    }
+}
+
+impl Subscriber for LevelFilterSubscriber {
+    fn register_callsite(
+        &self,
+        metadata: &'static Metadata<'static>,
+    ) -> Interest {
+        if self.inner.register_callsite(metadata).is_never() {
+            Interest::never()
+        } else {
+            Interest::sometimes()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= &self.level && self.inner.enabled(metadata)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::from_level(self.level))
+    }
+
+    fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+        self.inner.new_span(span)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.inner.record(span, values)
+    }
+
+    fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+        self.inner.record_follows_from(span, follows)
+    }
+
+    fn event(&self, event: &Event<'_>) {
+        self.inner.event(event)
+    }
+
+    fn enter(&self, span: &span::Id) {
+        self.inner.enter(span)
+    }
+
+    fn exit(&self, span: &span::Id) {
+        self.inner.exit(span)
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        self.inner.clone_span(id)
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        self.inner.try_close(id)
+    }
+}
@@ -0,0 +1,160 @@
+//! Future for a script running on a separate thread.
+use crate::Result;
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+#[derive(Default)]
+struct State {
+    result: Option<thread::Result<Result<()>>>,
+    waker: Option<Waker>,
+    finished: bool,
+}
+
+/// Future returned by [crate::ScriptFile::run_in_thread].
+///
+/// The pseudo-terminal API is blocking so the script runs on
+/// a dedicated thread and the future resolves when it finishes,
+/// a panic on the thread is resumed when the future is polled.
+///
+/// Dropping the future before the script finishes sets the
+/// cancel flag so the thread stops driving the program.
+pub struct RunFuture {
+    state: Arc<Mutex<State>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl RunFuture {
+    pub(crate) fn spawn(
+        func: impl FnOnce() -> Result<()> + Send + 'static,
+        cancel: Arc<AtomicBool>,
+    ) -> Self {
+        let state: Arc<Mutex<State>> = Default::default();
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            // Catch a panic so the waker is always called
+            let result = panic::catch_unwind(AssertUnwindSafe(func));
+            let mut state = shared.lock().unwrap();
+            state.result = Some(result);
+            state.finished = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        Self { state, cancel }
+    }
+}
+
+impl Drop for RunFuture {
+    fn drop(&mut self) {
+        if !self.state.lock().unwrap().finished {
+            self.cancel.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Future for RunFuture {
+    type Output = Result<()>;
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(panic)) => {
+                drop(state);
+                panic::resume_unwind(panic)
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RunFuture;
+    use std::{
+        future::Future,
+        panic,
+        pin::pin,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc,
+        },
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+        time::Duration,
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn run_future_result() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let future = RunFuture::spawn(|| Ok(()), Arc::clone(&cancel));
+        assert!(block_on(future).is_ok());
+        assert!(!cancel.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn run_future_drop_cancels() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancel);
+        let (tx, rx) = mpsc::channel();
+        let future = RunFuture::spawn(
+            move || {
+                while !flag.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                tx.send(()).unwrap();
+                Ok(())
+            },
+            Arc::clone(&cancel),
+        );
+        drop(future);
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn run_future_panic() {
+        let result = panic::catch_unwind(|| {
+            block_on(RunFuture::spawn(
+                || panic!("script panicked"),
+                Default::default(),
+            ))
+        });
+        let panic = result.unwrap_err();
+        assert_eq!(Some(&"script panicked"), panic.downcast_ref::<&str>());
+    }
+}
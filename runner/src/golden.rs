@@ -0,0 +1,200 @@
+//! Capture transcripts and compare them against golden files.
+use crate::{screen::Screen, Error, Result};
+use anticipate::log::LogWriter;
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Output read from the pseudo-terminal during a run.
+#[derive(Debug, Clone, Default)]
+pub struct Transcript(Arc<Mutex<Vec<u8>>>);
+
+impl Transcript {
+    /// Create an empty transcript.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Copy of the captured bytes.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn append(&self, data: &[u8]) {
+        self.0.lock().unwrap().extend_from_slice(data);
+    }
+}
+
+/// Log writer that records reads into a transcript and
+/// the screen used for screenshots.
+pub(crate) struct TranscriptLogWriter<O: LogWriter> {
+    inner: O,
+    transcript: Option<Transcript>,
+    screen: Option<Screen>,
+}
+
+impl<O: LogWriter> TranscriptLogWriter<O> {
+    /// Wrap a log writer.
+    pub fn new(
+        inner: O,
+        transcript: Option<Transcript>,
+        screen: Option<Screen>,
+    ) -> Self {
+        Self {
+            inner,
+            transcript,
+            screen,
+        }
+    }
+}
+
+impl<O: LogWriter> LogWriter for TranscriptLogWriter<O> {
+    fn log_read(&mut self, data: &[u8]) {
+        if let Some(transcript) = &self.transcript {
+            transcript.append(data);
+        }
+        if let Some(screen) = &self.screen {
+            screen.process(data);
+        }
+        self.inner.log_read(data);
+    }
+
+    fn log_write(&mut self, data: &[u8]) {
+        self.inner.log_write(data);
+    }
+}
+
+/// Golden file to compare transcripts against.
+#[derive(Debug, Clone)]
+pub struct Golden {
+    path: PathBuf,
+    strip_ansi: bool,
+}
+
+impl Golden {
+    /// Create a golden file reference.
+    pub fn new(path: impl AsRef<Path>, strip_ansi: bool) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            strip_ansi,
+        }
+    }
+
+    /// Path to the golden file.
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Normalize line endings and optionally strip ANSI
+    /// escape sequences.
+    pub fn normalize(&self, data: &[u8]) -> String {
+        let text = String::from_utf8_lossy(data);
+        let text = if self.strip_ansi {
+            Cow::Owned(strip_ansi(&text))
+        } else {
+            text
+        };
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    }
+
+    /// Write the transcript to the golden file.
+    pub fn update(&self, transcript: &[u8]) -> Result<()> {
+        std::fs::write(&self.path, self.normalize(transcript))?;
+        Ok(())
+    }
+
+    /// Compare the transcript with the golden file.
+    ///
+    /// Returns an error containing a line diff when the
+    /// contents do not match.
+    pub fn compare(&self, transcript: &[u8]) -> Result<()> {
+        let expected = self.normalize(&std::fs::read(&self.path)?);
+        let actual = self.normalize(transcript);
+        if expected != actual {
+            return Err(Error::GoldenMismatch(
+                self.path.clone(),
+                diff(&expected, &actual),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Remove ANSI escape sequences from text.
+fn strip_ansi(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            s.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Control sequence terminated by a final byte
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Operating system command terminated by BEL or ST
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    s
+}
+
+/// Line diff of expected and actual output.
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence table
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            out.push_str(&format!(" {}\n", a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push_str(&format!("-{}\n", a[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", b[j]));
+            j += 1;
+        }
+    }
+    for line in &a[i..] {
+        out.push_str(&format!("-{}\n", line));
+    }
+    for line in &b[j..] {
+        out.push_str(&format!("+{}\n", line));
+    }
+    out
+}
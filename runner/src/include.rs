@@ -0,0 +1,108 @@
+//! Resolve the files for includes declared in a script.
+use crate::{parser::IncludeRequest, Error, Include, Result};
+use std::path::{Path, PathBuf};
+
+/// Find the files for the includes of a script.
+///
+/// Patterns expand to every matching file in sorted order,
+/// an error is returned when an include matches no files.
+pub(crate) fn resolve(
+    requests: Vec<IncludeRequest<'_>>,
+    base: &Path,
+    include_roots: &[PathBuf],
+) -> Result<Vec<Include>> {
+    let mut includes = Vec::new();
+    for request in requests {
+        let text = request.text;
+        let tried = candidates(base, include_roots, text);
+        let paths = if is_pattern(text) {
+            let dirs = if Path::new(text).is_absolute() {
+                vec![PathBuf::new()]
+            } else {
+                directories(base, include_roots)
+            };
+            let mut paths = Vec::new();
+            for dir in dirs {
+                paths = expand(&dir, text)?;
+                if !paths.is_empty() {
+                    break;
+                }
+            }
+            paths
+        } else {
+            tried
+                .iter()
+                .find(|p| p.exists())
+                .cloned()
+                .into_iter()
+                .collect()
+        };
+        if paths.is_empty() {
+            return Err(Error::Include(
+                text.to_owned(),
+                tried,
+                request.location,
+            ));
+        }
+        for path in paths {
+            includes.push(Include {
+                index: request.index,
+                path: path.canonicalize()?,
+            });
+        }
+    }
+    Ok(includes)
+}
+
+/// Paths to try for an include in search order.
+fn candidates(
+    base: &Path,
+    include_roots: &[PathBuf],
+    text: &str,
+) -> Vec<PathBuf> {
+    let path = PathBuf::from(text);
+    if path.is_absolute() {
+        return vec![path];
+    }
+    directories(base, include_roots)
+        .into_iter()
+        .map(|dir| dir.join(&path))
+        .collect()
+}
+
+/// Directories relative includes are resolved against,
+/// the parent directory of the script file and then each
+/// include root.
+fn directories(base: &Path, include_roots: &[PathBuf]) -> Vec<PathBuf> {
+    let local = base.parent().map(Path::to_path_buf).unwrap_or_default();
+    std::iter::once(local)
+        .chain(include_roots.iter().cloned())
+        .collect()
+}
+
+/// Determine if the text contains wildcard characters.
+fn is_pattern(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// Files matching a pattern in a directory in sorted order.
+///
+/// Special characters in the directory are matched literally
+/// and hidden files are only matched by a leading dot.
+fn expand(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>> {
+    let dir = glob::Pattern::escape(&dir.to_string_lossy());
+    let pattern = Path::new(&dir).join(pattern);
+    let options = glob::MatchOptions {
+        require_literal_leading_dot: true,
+        ..Default::default()
+    };
+    let mut paths = Vec::new();
+    for entry in glob::glob_with(&pattern.to_string_lossy(), options)? {
+        let path = entry.map_err(std::io::Error::from)?;
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
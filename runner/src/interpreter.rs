@@ -1,22 +1,46 @@
+#[cfg(feature = "async")]
+use crate::RunFuture;
 use crate::{
-    resolve_path, Error, Instruction, Instructions, Result, ScriptParser,
+    builder::{output_argument, InterpreterOptionsBuilder},
+    filter::LevelFilterSubscriber,
+    golden::TranscriptLogWriter,
+    recording::{
+        program_exists, trim_exit, trim_intro, write_markers, Marker,
+        RecordingResult,
+    },
+    resolve_path,
+    screen::Screen,
+    Error, Instruction, Instructions, Location, Result, ScriptParser,
+    Transcript,
 };
 use anticipate::{
     log::{LogWriter, NoopLogWriter, PrefixLogWriter, StandardLogWriter},
     repl::ReplSession,
-    spawn_with_options, ControlCode, Expect, Regex, Session,
+    spawn_with_options, Any, Captures, ControlCode, Eof, Expect, Match,
+    Needle, NormalizeNewlines, OsProcess, Regex, Session,
 };
 use ouroboros::self_referencing;
 use probability::prelude::*;
-use std::io::{BufRead, Write};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::io::{BufRead, Read, Write};
 use std::{
     borrow::Cow,
+    collections::HashMap,
     path::{Path, PathBuf},
-    process::Command,
-    thread::sleep,
-    time::Duration,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, sleep},
+    time::{Duration, Instant},
 };
-use tracing::{span, Level};
+use tracing::{span, span::EnteredSpan, Level};
+
+#[cfg(unix)]
+use anticipate::process::unix::AttachedProcess;
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
 use unicode_segmentation::UnicodeSegmentation;
 
 const PROMPT: &str = "➜ ";
@@ -26,6 +50,11 @@ const COMMAND: &str = "bash -noprofile -norc";
 #[cfg(windows)]
 const COMMAND: &str = "pwsh -NoProfile -NonInteractive -NoLogo";
 
+#[cfg(unix)]
+const CONTINUATION: &str = "> ";
+#[cfg(windows)]
+const CONTINUATION: &str = ">> ";
+
 /// Source for probability distribution.
 struct Source<T>(T);
 
@@ -42,14 +71,34 @@ pub struct CinemaOptions {
     pub delay: u64,
     /// Type pragma command.
     pub type_pragma: bool,
+    /// Run the pragma command inside the recorded shell.
+    ///
+    /// Recordings always spawn the shell so a pragma is sent
+    /// as a command, when disabled the pragma is ignored.
+    pub run_pragma: bool,
     /// Deviation for gaussian delay modification.
     pub deviation: f64,
-    /// Shell to run.
-    pub shell: String,
+    /// Seed for typing delays so recordings are reproducible.
+    pub seed: Option<u64>,
+    /// Write a marker event to the cast for each section.
+    pub section_markers: bool,
+    /// Shell program to run.
+    pub shell_program: String,
+    /// Arguments for the shell program.
+    pub shell_args: Vec<String>,
     /// Terminal columns.
-    pub cols: u64,
+    ///
+    /// When not set the `COLUMNS` environment variable is used.
+    pub cols: Option<u64>,
     /// Terminal rows.
-    pub rows: u64,
+    ///
+    /// When not set the `LINES` environment variable is used.
+    pub rows: Option<u64>,
+    /// Flush after every keystroke.
+    ///
+    /// When disabled the typing delays are kept but the text
+    /// is only flushed once it has been typed.
+    pub flush_per_keystroke: bool,
 }
 
 impl Default for CinemaOptions {
@@ -57,32 +106,207 @@ impl Default for CinemaOptions {
         Self {
             delay: 75,
             type_pragma: false,
+            run_pragma: true,
             deviation: 15.0,
-            shell: COMMAND.to_string(),
-            cols: 80,
-            rows: 24,
+            seed: None,
+            section_markers: false,
+            shell_program: COMMAND
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+            shell_args: COMMAND
+                .split_whitespace()
+                .skip(1)
+                .map(|s| s.to_owned())
+                .collect(),
+            cols: None,
+            rows: None,
+            flush_per_keystroke: true,
         }
     }
 }
 
+impl CinemaOptions {
+    /// Terminal columns and rows.
+    ///
+    /// Sizes that are not set are read from the `COLUMNS` and
+    /// `LINES` environment variables, defaulting to 80 by 24.
+    pub fn size(&self) -> (u64, u64) {
+        self.size_with(|name| std::env::var(name).ok())
+    }
+
+    /// Terminal columns and rows reading variables with a lookup.
+    fn size_with(
+        &self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> (u64, u64) {
+        let env = |name: &str| {
+            lookup(name)
+                .and_then(|value| value.trim().parse().ok())
+                .filter(|value| *value > 0)
+        };
+        (
+            self.cols.or_else(|| env("COLUMNS")).unwrap_or(80),
+            self.rows.or_else(|| env("LINES")).unwrap_or(24),
+        )
+    }
+
+    /// Shell command line with each argument quoted.
+    pub fn shell_command(&self) -> String {
+        std::iter::once(&self.shell_program)
+            .chain(self.shell_args.iter())
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Quote a value for a POSIX shell when necessary.
+fn shell_quote(value: &str) -> Cow<'_, str> {
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if safe {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!("'{}'", value.replace('\'', "'\\''")))
+    }
+}
+
+/// Pattern used to match the prompt.
+#[derive(Debug, Clone)]
+pub enum PromptPattern {
+    /// Literal prompt text.
+    Literal(String),
+    /// Regular expression for dynamic prompts.
+    Regex(String),
+}
+
+impl PromptPattern {
+    /// Prompt text or regular expression.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Literal(value) | Self::Regex(value) => value,
+        }
+    }
+}
+
+impl From<String> for PromptPattern {
+    fn from(value: String) -> Self {
+        Self::Literal(value)
+    }
+}
+
+impl From<&str> for PromptPattern {
+    fn from(value: &str) -> Self {
+        Self::Literal(value.to_owned())
+    }
+}
+
+/// How comments are sent to the program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// Send comments as a line so the shell executes them.
+    #[default]
+    Execute,
+    /// Type comments then clear the line without executing.
+    Annotate,
+}
+
+/// Hook called with the instruction number and instruction.
+pub type InstructionHook = Box<dyn FnMut(usize, &Instruction<'_>) + Send>;
+
 /// Options for the interpreter.
 pub struct InterpreterOptions {
     /// Command to execute in the pty.
     pub command: String,
     /// Timeout for rexpect.
     pub timeout: Option<u64>,
+    /// Multiplier applied to the timeout for slow environments.
+    pub timeout_multiplier: f64,
+    /// Maximum duration for the entire script.
+    pub total_timeout: Option<Duration>,
+    /// Timeout waiting for the program to exit after the
+    /// script finishes, defaults to the timeout.
+    pub eof_timeout: Option<Duration>,
     /// Options for asciinema.
     pub cinema: Option<CinemaOptions>,
+    /// Output file for asciinema recordings.
+    pub output: Option<PathBuf>,
     /// Identifier.
     pub id: Option<String>,
     /// Prompt.
-    pub prompt: Option<String>,
+    pub prompt: Option<PromptPattern>,
+    /// Additional prompts matched by the waitany instruction.
+    ///
+    /// Matched as regular expressions when the prompt
+    /// is a regular expression.
+    pub prompts: Vec<String>,
     /// Echo to stdout.
     pub echo: bool,
     /// Format IO logged to stdout.
     pub format: bool,
+    /// Write logged IO to a file instead of stdout.
+    ///
+    /// The file is appended to so that logs from several
+    /// scripts are kept, IO is logged even when echo is off.
+    pub log_file: Option<PathBuf>,
     /// Print comments.
     pub print_comments: bool,
+    /// Style for printed comments.
+    pub comment_style: CommentStyle,
+    /// Match bare newlines in expect patterns against CRLF.
+    pub normalize_newlines: bool,
+    /// Custom log writer, takes precedence over echo and format.
+    pub log_writer: Option<Box<dyn LogWriter + Send>>,
+    /// Called with a 1-based number before each instruction executes.
+    pub on_instruction: Option<InstructionHook>,
+    /// Capture program output.
+    pub transcript: Option<Transcript>,
+    /// Flag to cancel execution.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Send end of transmission when the script finishes.
+    ///
+    /// Recordings always send end of transmission so that
+    /// asciinema exits.
+    pub send_eof: bool,
+    /// Shell commands to run before the script.
+    pub pre_commands: Vec<String>,
+    /// Shell commands to run after the script.
+    pub post_commands: Vec<String>,
+    /// Working directory for the spawned program.
+    pub working_dir: Option<PathBuf>,
+    /// Columns and rows for the pseudo-terminal.
+    ///
+    /// When not set the size is inherited.
+    pub size: Option<(u16, u16)>,
+    /// Maximum level for log events while the script runs.
+    ///
+    /// Events are still filtered by the installed subscriber
+    /// so this can only reduce the events that are logged.
+    pub log_level: Option<Level>,
+    /// Stop after an instruction whose name or argument
+    /// contains the text.
+    pub stop_after: Option<String>,
+    /// Continuation prompt shown by the program for incomplete input.
+    ///
+    /// When waiting for the prompt times out and the output ends
+    /// with the continuation prompt an error is returned that
+    /// explains the input was not terminated.
+    pub continuation_prompt: Option<String>,
+    /// Maximum length in bytes of a line sent to the program.
+    ///
+    /// Guards against interpolation producing enormous lines
+    /// that hang the program, checked before the line is sent.
+    pub max_line_length: Option<usize>,
+    /// Terminal of a program that was started elsewhere.
+    ///
+    /// When set the script runs against the terminal instead
+    /// of spawning the command, a pragma is ignored.
+    #[cfg(unix)]
+    pub attach_fd: Option<OwnedFd>,
 }
 
 impl Default for InterpreterOptions {
@@ -90,17 +314,45 @@ impl Default for InterpreterOptions {
         Self {
             command: COMMAND.to_owned(),
             prompt: None,
+            prompts: Vec::new(),
             timeout: Some(10000),
+            timeout_multiplier: 1.0,
+            total_timeout: None,
+            eof_timeout: None,
             cinema: None,
+            output: None,
             id: None,
             echo: false,
             format: false,
+            log_file: None,
             print_comments: false,
+            comment_style: CommentStyle::Execute,
+            normalize_newlines: false,
+            log_writer: None,
+            on_instruction: None,
+            transcript: None,
+            cancel: None,
+            send_eof: true,
+            pre_commands: Vec::new(),
+            post_commands: Vec::new(),
+            working_dir: None,
+            size: None,
+            log_level: None,
+            stop_after: None,
+            continuation_prompt: Some(CONTINUATION.to_owned()),
+            max_line_length: None,
+            #[cfg(unix)]
+            attach_fd: None,
         }
     }
 }
 
 impl InterpreterOptions {
+    /// Create a builder for interpreter options.
+    pub fn builder() -> InterpreterOptionsBuilder {
+        InterpreterOptionsBuilder::default()
+    }
+
     /// Create interpreter options.
     pub fn new(
         timeout: u64,
@@ -108,18 +360,32 @@ impl InterpreterOptions {
         format: bool,
         print_comments: bool,
     ) -> Self {
-        Self {
-            command: COMMAND.to_owned(),
-            prompt: None,
-            timeout: Some(timeout),
-            cinema: None,
-            id: None,
-            echo,
-            format,
-            print_comments,
+        Self::builder()
+            .timeout(timeout)
+            .echo(echo)
+            .format(format)
+            .print_comments(print_comments)
+            .build()
+    }
+
+    /// Ensure a line does not exceed the maximum length.
+    fn check_line_length(&self, line: &str) -> Result<()> {
+        match self.max_line_length {
+            Some(max) if line.len() > max => {
+                Err(Error::LineTooLong(line.len(), max))
+            }
+            _ => Ok(()),
         }
     }
 
+    /// Determine if execution has been cancelled.
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .map(|cancel| cancel.load(Ordering::SeqCst))
+            .unwrap_or(false)
+    }
+
     /// Create interpreter options for asciinema recording.
     pub fn new_recording(
         output: impl AsRef<Path>,
@@ -130,25 +396,13 @@ impl InterpreterOptions {
         format: bool,
         print_comments: bool,
     ) -> Self {
-        let mut command = format!(
-            "asciinema rec {:#?}",
-            output.as_ref().to_string_lossy(),
-        );
-        if overwrite {
-            command.push_str(" --overwrite");
-        }
-        command.push_str(&format!(" --rows={}", options.rows));
-        command.push_str(&format!(" --cols={}", options.cols));
-        Self {
-            command,
-            prompt: None,
-            timeout: Some(timeout),
-            cinema: Some(options),
-            id: None,
-            echo,
-            format,
-            print_comments,
-        }
+        Self::builder()
+            .recording(output, overwrite, options)
+            .timeout(timeout)
+            .echo(echo)
+            .format(format)
+            .print_comments(print_comments)
+            .build()
     }
 }
 
@@ -157,6 +411,7 @@ impl InterpreterOptions {
 pub struct ScriptFile {
     path: PathBuf,
     source: ScriptSource,
+    dependencies: Vec<PathBuf>,
 }
 
 impl ScriptFile {
@@ -174,6 +429,37 @@ impl ScriptFile {
     pub fn instructions(&self) -> &Instructions<'_> {
         self.source.borrow_instructions()
     }
+
+    /// Paths of all the files included by the script.
+    ///
+    /// Nested includes are listed after the file that includes
+    /// them and each path is only listed once.
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    /// Script instructions with includes expanded.
+    ///
+    /// Include instructions are replaced by the instructions
+    /// of the included file recursively.
+    pub fn instructions_flat(&self) -> Vec<&Instruction<'_>> {
+        let mut flat = Vec::new();
+        flatten(self.source.borrow_instructions(), &mut flat);
+        flat
+    }
+}
+
+fn flatten<'a>(
+    instructions: &'a [Instruction<'a>],
+    flat: &mut Vec<&'a Instruction<'a>>,
+) {
+    for instruction in instructions {
+        if let Instruction::Include(source) = instruction {
+            flatten(source.borrow_instructions(), flat);
+        } else {
+            flat.push(instruction);
+        }
+    }
 }
 
 #[self_referencing]
@@ -201,21 +487,72 @@ impl ScriptFile {
 
     /// Parse a single file.
     pub fn parse(path: impl AsRef<Path>) -> Result<ScriptFile> {
-        let source = Self::parse_source(path.as_ref())?;
+        Self::parse_with(path, &[], |_| {})
+    }
+
+    /// Parse a single file and transform the instructions.
+    ///
+    /// Include roots are searched in order when an include
+    /// is not found next to the file.
+    ///
+    /// The transform is applied to the instructions of each
+    /// included file before the parent instructions.
+    pub fn parse_with(
+        path: impl AsRef<Path>,
+        include_roots: &[PathBuf],
+        mut transform: impl FnMut(&mut Instructions<'_>),
+    ) -> Result<ScriptFile> {
+        let mut dependencies = Vec::new();
+        let source = Self::parse_source(
+            read_source(path.as_ref())?,
+            path.as_ref(),
+            include_roots,
+            &mut transform,
+            &mut dependencies,
+        )?;
         Ok(ScriptFile {
             path: path.as_ref().to_owned(),
             source,
+            dependencies,
         })
     }
 
-    fn parse_source(path: impl AsRef<Path>) -> Result<ScriptSource> {
+    /// Parse an in-memory source.
+    ///
+    /// Includes are resolved relative to the parent directory
+    /// of `base` as if the source had been read from that path.
+    pub fn parse_str(
+        source: &str,
+        base: impl AsRef<Path>,
+    ) -> Result<ScriptFile> {
+        let mut dependencies = Vec::new();
+        let source = Self::parse_source(
+            source.to_owned(),
+            base.as_ref(),
+            &[],
+            &mut |_| {},
+            &mut dependencies,
+        )?;
+        Ok(ScriptFile {
+            path: base.as_ref().to_owned(),
+            source,
+            dependencies,
+        })
+    }
+
+    fn parse_source(
+        source: String,
+        path: &Path,
+        include_roots: &[PathBuf],
+        transform: &mut dyn FnMut(&mut Instructions<'_>),
+        dependencies: &mut Vec<PathBuf>,
+    ) -> Result<ScriptSource> {
         let mut includes = Vec::new();
-        let source = std::fs::read_to_string(path.as_ref())?;
         let mut source = ScriptSourceTryBuilder {
             source,
             instructions_builder: |source| {
                 let (instructions, mut file_includes) =
-                    ScriptParser::parse_file(source, path.as_ref())?;
+                    ScriptParser::parse_file(source, path, include_roots)?;
                 includes.append(&mut file_includes);
                 Ok::<_, Error>(instructions)
             },
@@ -224,7 +561,16 @@ impl ScriptFile {
 
         let mut num_inserts = 0;
         for raw in includes {
-            let src = Self::parse_source(&raw.path)?;
+            if !dependencies.contains(&raw.path) {
+                dependencies.push(raw.path.clone());
+            }
+            let src = Self::parse_source(
+                read_source(&raw.path)?,
+                &raw.path,
+                include_roots,
+                transform,
+                dependencies,
+            )?;
             let instruction = Instruction::Include(src);
             source.with_instructions_mut(|i| {
                 let index = raw.index + num_inserts;
@@ -237,14 +583,125 @@ impl ScriptFile {
             });
         }
 
+        source.with_instructions_mut(|i| transform(i));
         Ok(source)
     }
 
+    /// Record the script using asciinema.
+    ///
+    /// The options must be created with [InterpreterOptions::new_recording]
+    /// and `trim_lines` are removed from the end of the cast file.
+    pub fn record(
+        &self,
+        mut options: InterpreterOptions,
+        trim_lines: u64,
+    ) -> Result<RecordingResult> {
+        let mut output =
+            options.output.clone().ok_or(Error::RecordingOutput)?;
+        // Relative output paths must not resolve against the
+        // working directory of the recording command
+        if options.working_dir.is_some() && output.is_relative() {
+            let absolute = std::env::current_dir()?.join(&output);
+            options.command = options.command.replacen(
+                &output_argument(&output),
+                &output_argument(&absolute),
+                1,
+            );
+            options.output = Some(absolute.clone());
+            output = absolute;
+        }
+        // Spawning a missing program fails in the child so it
+        // would otherwise surface as a timeout waiting for the prompt
+        let program = comma::parse_command(&options.command)
+            .and_then(|mut args| (!args.is_empty()).then(|| args.remove(0)))
+            .ok_or_else(|| Error::BadArguments(options.command.clone()))?;
+        if !program_exists(&program) {
+            return Err(Error::MissingTool(program));
+        }
+        let section_markers = options
+            .cinema
+            .as_ref()
+            .is_some_and(|cinema| cinema.section_markers);
+        let Executed {
+            mut markers,
+            hidden,
+        } = self.run_executed(options)?;
+        if trim_lines > 0 {
+            trim_exit(&output, trim_lines)?;
+        }
+        if hidden {
+            if let Some(offset) = trim_intro(&output)? {
+                for marker in markers.iter_mut() {
+                    marker.time = (marker.time - offset).max(0.0);
+                }
+            }
+        }
+        if section_markers && !markers.is_empty() {
+            write_markers(&output, &markers)?;
+        }
+        Ok(RecordingResult::new(output)?)
+    }
+
     /// Execute the command and instructions in a pseudo-terminal.
     pub fn run(&self, options: InterpreterOptions) -> Result<()> {
-        let cmd = options.command.clone();
+        self.run_executed(options).map(|_| ())
+    }
+
+    /// Execute the script and return what happened for recordings.
+    fn run_executed(&self, options: InterpreterOptions) -> Result<Executed> {
+        let (level, id) = (options.log_level, options.id.clone());
+        self.run_scoped(level, id, || self.execute(options))
+    }
 
-        let span = if let Some(id) = &options.id {
+    /// Execute the instructions in a shared session.
+    ///
+    /// The program is already running so only the options for
+    /// executing instructions are used, such as the timeouts,
+    /// hooks and commands to run before and after the script.
+    /// A pragma is ignored.
+    pub fn run_shared(
+        &self,
+        session: &mut SharedSession,
+        options: InterpreterOptions,
+    ) -> Result<()> {
+        let (level, id) = (options.log_level, options.id.clone());
+        self.run_scoped(level, id, || self.execute_shared(session, options))
+    }
+
+    /// Run a function filtering log events in a span for the script.
+    fn run_scoped<T>(
+        &self,
+        level: Option<Level>,
+        id: Option<String>,
+        func: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if let Some(level) = level {
+            LevelFilterSubscriber::scope(level, || self.run_span(id, func))
+        } else {
+            self.run_span(id, func)
+        }
+    }
+
+    /// Execute the script on a separate thread.
+    ///
+    /// The returned future resolves with the result so the
+    /// calling task is not blocked, each script still uses a
+    /// thread. Dropping the future before the script finishes
+    /// sets the cancel flag from the options, or a new flag
+    /// when none was given, so the script stops.
+    #[cfg(feature = "async")]
+    pub fn run_in_thread(self, mut options: InterpreterOptions) -> RunFuture {
+        let cancel = options.cancel.get_or_insert_with(Default::default);
+        let cancel = Arc::clone(cancel);
+        RunFuture::spawn(move || self.run(options), cancel)
+    }
+
+    fn run_span<T>(
+        &self,
+        id: Option<String>,
+        func: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let span = if let Some(id) = &id {
             span!(Level::DEBUG, "run", id = id)
         } else {
             span!(Level::DEBUG, "run")
@@ -252,16 +709,27 @@ impl ScriptFile {
 
         let _enter = span.enter();
 
+        tracing::info!(path = ?self.path, "start");
+        let result = func();
+        match &result {
+            Ok(_) => tracing::info!(ok = true, "finish"),
+            Err(e) => tracing::info!(ok = false, error = %e, "finish"),
+        }
+        result
+    }
+
+    fn execute(&self, mut options: InterpreterOptions) -> Result<Executed> {
         let instructions = self.source.borrow_instructions();
         let is_cinema = options.cinema.is_some();
-
-        let prompt =
-            options.prompt.clone().unwrap_or_else(|| PROMPT.to_owned());
-        std::env::set_var("PS1", &prompt);
+        let prompt = spawn_prompt(&options);
 
         if let Some(cinema) = &options.cinema {
             // Export a vanilla shell for asciinema
-            let shell = format!("PS1='{}' {}", &prompt, cinema.shell);
+            let shell = format!(
+                "PS1={} {}",
+                shell_quote(shell_prompt(&prompt)),
+                cinema.shell_command()
+            );
             std::env::set_var("SHELL", shell);
         }
 
@@ -275,41 +743,228 @@ impl ScriptFile {
         let exec_cmd = if let (false, Some(pragma)) = (is_cinema, &pragma) {
             pragma.as_ref().to_owned()
         } else {
-            cmd.to_owned()
+            options.command.clone()
         };
 
-        tracing::info!(exec = %exec_cmd, "run");
-
-        let timeout = options
-            .timeout
-            .as_ref()
-            .map(|val| Duration::from_millis(*val));
-
-        let cmd = parse_command(&exec_cmd)?;
-        if !options.echo && !options.format {
-            let pty: Session<NoopLogWriter> =
-                spawn_with_options(cmd, None, timeout)?;
-            start(pty, prompt, options, pragma, instructions)?;
-        } else if options.echo && !options.format {
-            let pty = spawn_with_options(
-                cmd,
-                Some(StandardLogWriter::default()),
-                timeout,
-            )?;
-            start(pty, prompt, options, pragma, instructions)?;
-        } else if options.echo && options.format {
-            let pty = spawn_with_options(
-                cmd,
-                Some(PrefixLogWriter::default()),
-                timeout,
+        run_setup_commands(instructions, &options)?;
+        let screen = has_screenshot(instructions).then(|| {
+            let (cols, rows) = options
+                .size
+                .or_else(|| {
+                    options
+                        .cinema
+                        .as_ref()
+                        .map(|c| c.size())
+                        .map(|(cols, rows)| (cols as u16, rows as u16))
+                })
+                .unwrap_or((80, 24));
+            Screen::new(cols, rows)
+        });
+        #[cfg(unix)]
+        if let Some(fd) = options.attach_fd.take() {
+            if is_cinema {
+                return Err(Error::Attached("recording"));
+            }
+            if let Some(pragma) = &pragma {
+                tracing::warn!(pragma = %pragma, "pragma ignored when attached");
+            }
+            let log_writer = TranscriptLogWriter::new(
+                boxed_log_writer(&mut options)?,
+                options.transcript.clone(),
+                screen.clone(),
+            );
+            let pty = Session::attach(
+                fd,
+                Some(log_writer),
+                expect_timeout(&options),
             )?;
-            start(pty, prompt, options, pragma, instructions)?;
+            return start(
+                pty,
+                prompt,
+                options,
+                None,
+                instructions,
+                screen,
+                &self.path,
+            );
         }
+        let log_writer = TranscriptLogWriter::new(
+            boxed_log_writer(&mut options)?,
+            options.transcript.clone(),
+            screen.clone(),
+        );
+        let pty = spawn_program(&exec_cmd, &options, log_writer)?;
+        start(
+            pty,
+            prompt,
+            options,
+            pragma,
+            instructions,
+            screen,
+            &self.path,
+        )
+    }
+}
+
+impl ScriptFile {
+    fn execute_shared(
+        &self,
+        session: &mut SharedSession,
+        mut options: InterpreterOptions,
+    ) -> Result<()> {
+        let instructions = self.source.borrow_instructions();
+        if let Some(Instruction::Pragma(cmd)) = instructions.first() {
+            tracing::warn!(pragma = %cmd, "pragma ignored in shared session");
+        }
+        run_setup_commands(instructions, &options)?;
+        session.p.set_expect_timeout(expect_timeout(&options));
 
+        let screen = Some(session.screen.clone());
+        let mut state = ExecState::new(&mut options, screen, &self.path);
+        let pre = wrap_commands(&options.pre_commands);
+        let post = wrap_commands(&options.post_commands);
+        [pre.as_slice(), instructions, post.as_slice()]
+            .into_iter()
+            .try_for_each(|list| {
+                exec(&mut session.p, list, &options, None, &mut state)
+            })?;
+        state.section.take();
+
+        if let Some(quit) = state.quit.take() {
+            session.quit = Some(quit);
+        }
         Ok(())
     }
 }
 
+/// Program shared by scripts that run one after another.
+///
+/// Shell state such as environment variables and the working
+/// directory is kept between scripts. A script that exits or
+/// crashes the program also fails every script that follows.
+pub struct SharedSession {
+    p: ReplSession<TranscriptLogWriter<Box<dyn LogWriter + Send>>>,
+    options: InterpreterOptions,
+    screen: Screen,
+    quit: Option<String>,
+}
+
+impl SharedSession {
+    /// Spawn the program for the session.
+    ///
+    /// The command, prompt, log writer, transcript, size and
+    /// working directory are taken from the options.
+    pub fn spawn(mut options: InterpreterOptions) -> Result<Self> {
+        let prompt = spawn_prompt(&options);
+        let (cols, rows) = options.size.unwrap_or((80, 24));
+        let screen = Screen::new(cols, rows);
+        let log_writer = TranscriptLogWriter::new(
+            boxed_log_writer(&mut options)?,
+            options.transcript.clone(),
+            Some(screen.clone()),
+        );
+        let session = spawn_program(&options.command, &options, log_writer)?;
+        let p = repl_session(session, &prompt, &options)?;
+        Ok(Self {
+            p,
+            options,
+            screen,
+            quit: None,
+        })
+    }
+
+    /// Exit the program.
+    ///
+    /// Sends the quit command of the last script that declared
+    /// one, otherwise end of transmission unless disabled.
+    pub fn finish(mut self) -> Result<()> {
+        set_eof_timeout(&mut self.p, &self.options);
+        shutdown(&mut self.p, &self.options, self.quit.as_deref())
+    }
+}
+
+/// Log writer selected by the options.
+fn boxed_log_writer(
+    options: &mut InterpreterOptions,
+) -> Result<Box<dyn LogWriter + Send>> {
+    Ok(match options.log_writer.take() {
+        Some(log_writer) => log_writer,
+        None => match &options.log_file {
+            Some(path) => file_log_writer(path, options.format)?,
+            None if options.echo && options.format => {
+                Box::new(PrefixLogWriter::with_writer(std::io::stdout()))
+            }
+            None if options.echo => {
+                Box::new(StandardLogWriter::with_writer(std::io::stdout()))
+            }
+            None => Box::new(NoopLogWriter),
+        },
+    })
+}
+
+/// Prompt selected by the options.
+///
+/// The prompt is exported as `PS1` so that a spawned shell
+/// uses it.
+fn spawn_prompt(options: &InterpreterOptions) -> PromptPattern {
+    let prompt = options
+        .prompt
+        .clone()
+        .unwrap_or_else(|| PromptPattern::Literal(PROMPT.to_owned()));
+    std::env::set_var("PS1", shell_prompt(&prompt));
+    prompt
+}
+
+/// Text for `PS1`, a regular expression can not be used
+/// so the default prompt is the fallback.
+fn shell_prompt(prompt: &PromptPattern) -> &str {
+    match prompt {
+        PromptPattern::Literal(prompt) => prompt,
+        PromptPattern::Regex(_) => PROMPT,
+    }
+}
+
+/// Timeout for expect calls scaled by the multiplier.
+fn expect_timeout(options: &InterpreterOptions) -> Option<Duration> {
+    options.timeout.as_ref().map(|val| {
+        Duration::from_millis(*val).mul_f64(options.timeout_multiplier)
+    })
+}
+
+/// Spawn a program in the working directory from the options.
+fn spawn_program<O: LogWriter>(
+    command: &str,
+    options: &InterpreterOptions,
+    log_writer: O,
+) -> Result<Session<O>> {
+    tracing::info!(exec = %command, "spawn");
+    let mut cmd = parse_command(command)?;
+    if let Some(dir) = &options.working_dir {
+        cmd.current_dir(dir);
+    }
+    Ok(spawn_with_options(
+        cmd,
+        Some(log_writer),
+        expect_timeout(options),
+    )?)
+}
+
+/// Wrap a session to expect the prompt and resize the
+/// terminal to the size from the options.
+fn repl_session<O: LogWriter, P: Program>(
+    session: Session<O, P>,
+    prompt: &PromptPattern,
+    options: &InterpreterOptions,
+) -> Result<ReplSession<O, P>> {
+    let mut p =
+        ReplSession::new(session, prompt.as_str().to_owned(), None, false);
+    p.set_prompt_regex(matches!(prompt, PromptPattern::Regex(_)));
+    if let Some((cols, rows)) = options.size {
+        resize(&mut p, cols, rows)?;
+    }
+    Ok(p)
+}
+
 fn parse_command(cmd: &str) -> Result<Command> {
     let mut parts = comma::parse_command(cmd)
         .ok_or(Error::BadArguments(cmd.to_owned()))?;
@@ -319,14 +974,135 @@ fn parse_command(cmd: &str) -> Result<Command> {
     Ok(command)
 }
 
-fn start<O: LogWriter>(
-    session: Session<O>,
-    prompt: String,
-    options: InterpreterOptions,
-    pragma: Option<Cow<'_, str>>,
+/// Run the setup commands at the top of a script in order.
+///
+/// Commands at the top of included files are hoisted so they
+/// run after the commands of the script before the program
+/// is started.
+///
+/// Commands run to completion outside of the terminal and
+/// a command that fails stops the script.
+fn run_setup_commands(
     instructions: &[Instruction<'_>],
+    options: &InterpreterOptions,
 ) -> Result<()> {
-    let mut p = ReplSession::new(session, prompt, None, false);
+    let started = Instant::now();
+    let mut commands = Vec::new();
+    setup_commands(instructions, &mut commands);
+    for command in commands {
+        run_setup_command(command, options, started)?;
+    }
+    Ok(())
+}
+
+/// Collect the setup commands of a script and its includes.
+fn setup_commands<'a>(
+    instructions: &'a [Instruction<'a>],
+    commands: &mut Vec<&'a str>,
+) {
+    for instruction in instructions {
+        match instruction {
+            Instruction::Pragma(_) | Instruction::Comment(_) => {}
+            Instruction::Exec(command) => commands.push(command),
+            _ => break,
+        }
+    }
+    for instruction in instructions {
+        if let Instruction::Include(source) = instruction {
+            setup_commands(source.borrow_instructions(), commands);
+        }
+    }
+}
+
+/// Run a setup command and wait for it to exit.
+///
+/// The command is killed when it exceeds the timeout, when
+/// the total timeout since `started` elapses or when
+/// execution is cancelled.
+fn run_setup_command(
+    command: &str,
+    options: &InterpreterOptions,
+    started: Instant,
+) -> Result<()> {
+    tracing::info!(command = %command, "exec");
+    let mut cmd = parse_command(command)?;
+    if let Some(dir) = &options.working_dir {
+        cmd.current_dir(dir);
+    }
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read output on threads so a full pipe does not block the command
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+
+    let timeout = expect_timeout(options);
+    let total_timeout = options
+        .total_timeout
+        .map(|limit| limit.mul_f64(options.timeout_multiplier));
+    let spawned = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let error = if options.is_cancelled() {
+            Some(Error::Cancelled)
+        } else if let Some(limit) =
+            total_timeout.filter(|limit| started.elapsed() >= *limit)
+        {
+            Some(Error::TotalTimeout(limit))
+        } else {
+            timeout
+                .filter(|limit| spawned.elapsed() >= *limit)
+                .map(|limit| Error::ExecTimeout(command.to_owned(), limit))
+        };
+        if let Some(error) = error {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error);
+        }
+        sleep(Duration::from_millis(10));
+    };
+
+    let output = |pipe: Option<thread::JoinHandle<Vec<u8>>>| {
+        let bytes = pipe.and_then(|p| p.join().ok()).unwrap_or_default();
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    tracing::debug!(
+        stdout = %output(stdout),
+        stderr = %output(stderr),
+        "exec"
+    );
+    if !status.success() {
+        return Err(Error::ExecFailed(command.to_string(), status));
+    }
+    Ok(())
+}
+
+/// Read a pipe to the end on a separate thread.
+fn read_pipe(
+    mut pipe: impl Read + Send + 'static,
+) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = pipe.read_to_end(&mut bytes);
+        bytes
+    })
+}
+
+fn start<O: LogWriter, P: Program>(
+    session: Session<O, P>,
+    prompt: PromptPattern,
+    mut options: InterpreterOptions,
+    pragma: Option<Cow<'_, str>>,
+    instructions: &[Instruction<'_>],
+    screen: Option<Screen>,
+    script: &Path,
+) -> Result<Executed> {
+    let mut p = repl_session(session, &prompt, &options)?;
+    let mut state = ExecState::new(&mut options, screen, script);
 
     if options.cinema.is_some() {
         p.expect_prompt()?;
@@ -335,85 +1111,862 @@ fn start<O: LogWriter>(
         tracing::debug!("ready");
     }
 
-    exec(
-        &mut p,
-        instructions,
-        &options,
-        pragma.as_ref().map(|i| i.as_ref()),
-    )?;
+    let pragma = pragma.as_ref().map(|i| i.as_ref());
+    let pre = wrap_commands(&options.pre_commands);
+    let post = wrap_commands(&options.post_commands);
+    let result = [pre.as_slice(), instructions, post.as_slice()]
+        .into_iter()
+        .try_for_each(|list| {
+            exec(&mut p, list, &options, pragma, &mut state)
+        });
 
+    set_eof_timeout(&mut p, &options);
+
+    if let Err(Error::Cancelled) = &result {
+        tracing::debug!("cancelled");
+        // Program may have exited already so ignore errors
+        if let Some(quit) = &state.quit {
+            let _ = p.send_line(quit);
+        } else {
+            let _ = p.send(ControlCode::EndOfTransmission);
+        }
+        if options.cinema.is_some() {
+            // Wait for asciinema to finish writing the cast
+            let _ = p.expect(Eof);
+        }
+    }
+    result?;
+    state.section.take();
     if options.cinema.is_some() {
+        reveal(&mut p, &mut state)?;
+    }
+
+    shutdown(&mut p, &options, state.quit.as_deref())?;
+    Ok(Executed {
+        markers: state.markers,
+        hidden: state.hidden,
+    })
+}
+
+/// Outcome of executing a script.
+struct Executed {
+    /// Marker for each section.
+    markers: Vec<Marker>,
+    /// Whether eval commands at the start of a recording
+    /// must be cut from the cast.
+    hidden: bool,
+}
+
+/// Clear the screen before the first visible instruction.
+///
+/// When eval commands were run at the start of a recording
+/// the cast is cut at the clear so they are not shown.
+fn reveal<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    state: &mut ExecState,
+) -> Result<()> {
+    if !state.visible {
+        state.visible = true;
+        if state.hidden {
+            p.send_line("clear")?;
+            p.expect_prompt()?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether an instruction writes nothing to a recording.
+fn is_silent(cmd: &Instruction<'_>, options: &InterpreterOptions) -> bool {
+    match cmd {
+        Instruction::Eval(_)
+        | Instruction::Exec(_)
+        | Instruction::Include(_)
+        | Instruction::Section(_)
+        | Instruction::Require { .. }
+        | Instruction::Lazy
+        | Instruction::Greedy => true,
+        Instruction::Comment(_) => !options.print_comments,
+        Instruction::Pragma(_) => options
+            .cinema
+            .as_ref()
+            .is_some_and(|cinema| !cinema.run_pragma),
+        _ => false,
+    }
+}
+
+/// Program shutdown may take longer than the expect calls.
+fn set_eof_timeout<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    options: &InterpreterOptions,
+) {
+    if let Some(limit) = options.eof_timeout {
+        p.set_expect_timeout(Some(limit.mul_f64(options.timeout_multiplier)));
+    }
+}
+
+/// Send the quit command or end of transmission after the script.
+///
+/// After the quit command the program must exit with a zero
+/// status.
+fn shutdown<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    options: &InterpreterOptions,
+    quit: Option<&str>,
+) -> Result<()> {
+    if let Some(quit) = quit {
+        tracing::debug!(quit = %quit, "quit");
+        p.send_line(quit)?;
+        p.expect(Eof)?;
+        // Attached programs are not children so can not be waited on
+        match wait_exit(p) {
+            Ok(0) | Err(Error::Attached(_)) => {}
+            Ok(status) => {
+                return Err(Error::ExitStatus {
+                    expected: 0,
+                    actual: status,
+                    quit: Some(quit.to_owned()),
+                })
+            }
+            Err(e) => return Err(e),
+        }
+    } else if options.cinema.is_some() {
         tracing::debug!("exit");
         p.send(ControlCode::EndOfTransmission)?;
-    } else {
+    } else if options.send_eof {
         tracing::debug!("eof");
         // If it's not a shell, ie: has a pragma command
         // which is a script this will fail with I/O error
         // but we can safely ignore it
         let _ = p.send(ControlCode::EndOfTransmission);
     }
+    Ok(())
+}
 
+/// State accumulated while executing instructions.
+#[derive(Default)]
+struct ExecState {
+    /// Command sent to quit the program.
+    quit: Option<String>,
+    /// Variables captured from program output.
+    variables: HashMap<String, String>,
+    /// Exit status of the program.
+    exit_status: Option<i32>,
+    /// Start time and limit for the entire script.
+    total_timeout: Option<(Instant, Duration)>,
+    /// Number of instructions executed.
+    count: usize,
+    /// Whether the lazy expect algorithm is used.
+    lazy: bool,
+    /// Hook called before each instruction.
+    on_instruction: Option<InstructionHook>,
+    /// Seeded generator for typing delays.
+    rng: Option<StdRng>,
+    /// Time the program was started.
+    started: Option<Instant>,
+    /// Span for the current section.
+    section: Option<EnteredSpan>,
+    /// Marker for each section.
+    markers: Vec<Marker>,
+    /// Rendered output for screenshots.
+    screen: Option<Screen>,
+    /// Path of the script for resolving relative paths.
+    #[cfg_attr(not(feature = "screenshot"), allow(dead_code))]
+    script: PathBuf,
+    /// Whether execution stopped early.
+    stopped: bool,
+    /// Eval commands ran before any visible instruction.
+    hidden: bool,
+    /// Whether a visible instruction has been executed.
+    visible: bool,
+}
+
+impl ExecState {
+    fn new(
+        options: &mut InterpreterOptions,
+        screen: Option<Screen>,
+        script: &Path,
+    ) -> Self {
+        Self {
+            total_timeout: options.total_timeout.map(|limit| {
+                (Instant::now(), limit.mul_f64(options.timeout_multiplier))
+            }),
+            on_instruction: options.on_instruction.take(),
+            rng: options
+                .cinema
+                .as_ref()
+                .and_then(|cinema| cinema.seed)
+                .map(StdRng::seed_from_u64),
+            started: Some(Instant::now()),
+            screen,
+            script: script.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Error if the total timeout has elapsed.
+    fn check_total_timeout(&self) -> Result<()> {
+        if let Some((started, limit)) = self.total_timeout {
+            if started.elapsed() >= limit {
+                return Err(Error::TotalTimeout(limit));
+            }
+        }
+        Ok(())
+    }
+
+    /// Cap a delay at the remaining total timeout.
+    fn remaining(&self, delay: Duration) -> Duration {
+        match self.total_timeout {
+            Some((started, limit)) => {
+                delay.min(limit.saturating_sub(started.elapsed()))
+            }
+            None => delay,
+        }
+    }
+}
+
+/// Read a script file, errors include the path.
+fn read_source(path: &Path) -> Result<String> {
+    std::fs::read_to_string(path).map_err(|e| Error::Read(e, path.to_owned()))
+}
+
+/// Log writer that appends to a file.
+fn file_log_writer(
+    path: &Path,
+    format: bool,
+) -> Result<Box<dyn LogWriter + Send>> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    Ok(if format {
+        Box::new(PrefixLogWriter::with_writer(file))
+    } else {
+        Box::new(StandardLogWriter::with_writer(file))
+    })
+}
+
+/// Synthetic instructions to send commands and wait for the prompt.
+fn wrap_commands(commands: &[String]) -> Vec<Instruction<'_>> {
+    commands
+        .iter()
+        .flat_map(|cmd| [Instruction::SendLine(cmd), Instruction::Wait])
+        .collect()
+}
+
+fn type_text<O: LogWriter, P: Program>(
+    pty: &mut ReplSession<O, P>,
+    text: &str,
+    cinema: &CinemaOptions,
+    options: &InterpreterOptions,
+    rng: &mut Option<StdRng>,
+) -> Result<()> {
+    type_chars(pty, text, cinema, options, rng)?;
+    pty.send(pty.line_ending().as_bytes())?;
+    pty.flush()?;
     Ok(())
 }
 
-fn type_text<O: LogWriter>(
-    pty: &mut ReplSession<O>,
+/// Type text without a trailing newline.
+fn type_chars<W: Write>(
+    pty: &mut W,
     text: &str,
     cinema: &CinemaOptions,
+    options: &InterpreterOptions,
+    rng: &mut Option<StdRng>,
 ) -> Result<()> {
     for c in UnicodeSegmentation::graphemes(text, true) {
-        pty.send(c)?;
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+
+        pty.write_all(c.as_bytes())?;
+        if cinema.flush_per_keystroke {
+            pty.flush()?;
+        }
+        sleep(Duration::from_millis(typing_delay(cinema, rng)));
+    }
+
+    if !cinema.flush_per_keystroke {
         pty.flush()?;
+    }
+    Ok(())
+}
 
-        let mut source = Source(rand::rngs::OsRng);
-        let gaussian = Gaussian::new(0.0, cinema.deviation);
-        let drift = gaussian.sample(&mut source);
+/// Delay after typing a character with gaussian drift.
+///
+/// The operating system generator is used unless the
+/// recording has a seed.
+fn typing_delay(cinema: &CinemaOptions, rng: &mut Option<StdRng>) -> u64 {
+    let gaussian = Gaussian::new(0.0, cinema.deviation);
+    let drift = if let Some(rng) = rng {
+        gaussian.sample(&mut Source(rng))
+    } else {
+        gaussian.sample(&mut Source(rand::rngs::OsRng))
+    };
 
-        let delay = if (drift as u64) < cinema.delay {
-            let drift = drift as i64;
-            if drift < 0 {
-                cinema.delay - drift.unsigned_abs()
-            } else {
-                cinema.delay + drift as u64
+    if (drift as u64) < cinema.delay {
+        let drift = drift as i64;
+        if drift < 0 {
+            cinema.delay - drift.unsigned_abs()
+        } else {
+            cinema.delay + drift as u64
+        }
+    } else {
+        cinema.delay + drift.abs() as u64
+    }
+}
+
+/// Sleep delay with uniform jitter either side of the base.
+///
+/// Uses the same generator as the typing delays so seeded
+/// recordings are reproducible.
+fn sleep_jitter(base: u64, jitter: u64, rng: &mut Option<StdRng>) -> u64 {
+    if jitter == 0 {
+        return base;
+    }
+    let range = 0..=jitter.saturating_mul(2);
+    let offset = if let Some(rng) = rng {
+        rng.gen_range(range)
+    } else {
+        rand::rngs::OsRng.gen_range(range)
+    };
+    base.saturating_add(offset).saturating_sub(jitter)
+}
+
+/// Block until the program exits and return the exit status.
+fn wait_exit<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+) -> Result<i32> {
+    p.get_process_mut().wait_exit()
+}
+
+/// Resize the terminal of the program.
+///
+/// When recording the terminal running asciinema is resized
+/// which propagates the new size to the recorded shell.
+fn resize<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    cols: u16,
+    rows: u16,
+) -> Result<()> {
+    tracing::debug!(cols = %cols, rows = %rows, "resize");
+    p.get_process_mut().resize(cols, rows)
+}
+
+/// Control of the program that depends on how it was started.
+trait Program {
+    /// Block until the program exits and return the exit status.
+    fn wait_exit(&mut self) -> Result<i32>;
+
+    /// Change the size of the terminal.
+    fn resize(&mut self, cols: u16, rows: u16) -> Result<()>;
+
+    /// Send a signal to the program.
+    #[cfg(unix)]
+    fn send_signal(&mut self, signal: anticipate::Signal) -> Result<()>;
+}
+
+#[cfg(unix)]
+impl Program for OsProcess {
+    /// Processes terminated by a signal report `128 + signal`.
+    fn wait_exit(&mut self) -> Result<i32> {
+        use anticipate::WaitStatus;
+        loop {
+            let status = self.wait().map_err(std::io::Error::from)?;
+            match status {
+                WaitStatus::Exited(_, code) => return Ok(code),
+                WaitStatus::Signaled(_, signal, _) => {
+                    return Ok(128 + signal as i32)
+                }
+                _ => {}
             }
+        }
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.set_window_size(cols, rows)
+            .map_err(std::io::Error::from)?;
+        Ok(())
+    }
+
+    fn send_signal(&mut self, signal: anticipate::Signal) -> Result<()> {
+        self.signal(signal)?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Program for OsProcess {
+    fn wait_exit(&mut self) -> Result<i32> {
+        let code = self.wait(None).map_err(anticipate::Error::from)?;
+        Ok(code as i32)
+    }
+
+    /// Resizing is not supported on windows yet.
+    fn resize(&mut self, _cols: u16, _rows: u16) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The program was started elsewhere so only IO is available.
+#[cfg(unix)]
+impl Program for AttachedProcess {
+    fn wait_exit(&mut self) -> Result<i32> {
+        Err(Error::Attached("waiting for exit"))
+    }
+
+    fn resize(&mut self, _cols: u16, _rows: u16) -> Result<()> {
+        Err(Error::Attached("resize"))
+    }
+
+    fn send_signal(&mut self, _signal: anticipate::Signal) -> Result<()> {
+        Err(Error::Attached("signal"))
+    }
+}
+
+/// Evaluate an assertion against the captured variables.
+///
+/// Supports `$NAME == value` for equality and `$NAME =~ regex`
+/// for a pattern match.
+fn assert_variable(
+    text: &str,
+    variables: &HashMap<String, String>,
+) -> Result<()> {
+    // Parsed scripts are validated so the location is only
+    // relative to the text for instructions built in code
+    let (name, operator, expected) = ScriptParser::parse_assertion(text)
+        .ok_or_else(|| {
+            Error::InvalidAssertion(
+                text.trim().to_owned(),
+                Location::new(text, 0..text.len()),
+            )
+        })?;
+
+    let actual = ScriptParser::interpolate(name, variables)?;
+    let matched = if operator == "==" {
+        actual == expected
+    } else {
+        let found = Regex(expected).check(actual.as_bytes(), false)?;
+        !found.is_empty()
+    };
+
+    tracing::debug!(actual = %actual, expected = %expected, "assert");
+    if !matched {
+        return Err(Error::AssertionFailed {
+            expected: expected.to_owned(),
+            actual: actual.into_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Check a program is installed and meets the minimum version.
+///
+/// The version is the first dotted number in the output of
+/// `PROGRAM --version`, falling back to the first number.
+fn require(program: &str, version: Option<&str>) -> Result<()> {
+    let output = match Command::new(program).arg("--version").output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::MissingTool(program.to_owned()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let Some(required) = version else {
+        return Ok(());
+    };
+
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push('\n');
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    let mut found = None;
+    for pattern in ["[0-9]+(?:\\.[0-9]+)+", "[0-9]+"] {
+        if let Some(m) = Regex(pattern).check(text.as_bytes(), false)?.first()
+        {
+            found = Some(&text[m.start()..m.end()]);
+            break;
+        }
+    }
+
+    let numbers = |version: &str| -> Vec<u64> {
+        version.split('.').map(|n| n.parse().unwrap_or(0)).collect()
+    };
+    let mismatch = |found: &str| Error::VersionMismatch {
+        program: program.to_owned(),
+        required: required.to_owned(),
+        found: found.to_owned(),
+    };
+    let found = found.ok_or_else(|| mismatch("unknown"))?;
+    let (mut have, mut want) = (numbers(found), numbers(required));
+    let len = have.len().max(want.len());
+    have.resize(len, 0);
+    want.resize(len, 0);
+    tracing::debug!(program = %program, version = %found, "require");
+    if have < want {
+        return Err(mismatch(found));
+    }
+    Ok(())
+}
+
+/// Parse a control code from a letter, caret notation or name.
+///
+/// Names are case-insensitive and `EOF` is an alias for `EOT`.
+fn parse_control_code(value: &str) -> Result<ControlCode> {
+    let value = value.trim();
+    let mut chars = value.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => ControlCode::try_from(c).ok(),
+        _ => ControlCode::try_from(value).ok(),
+    };
+    code.or_else(|| {
+        let name = if value.eq_ignore_ascii_case("EOF") {
+            "EOT"
         } else {
-            cinema.delay + drift.abs() as u64
+            value
         };
+        ControlCode::all()
+            .iter()
+            .find(|(_, n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(code, _, _)| *code)
+    })
+    .ok_or_else(|| Error::InvalidControlCode(value.to_owned()))
+}
 
-        sleep(Duration::from_millis(delay));
+/// Hand control to the user until the escape key is pressed.
+///
+/// Skipped when recording or when stdin is not a terminal
+/// so that scripts never block waiting for input.
+#[cfg(unix)]
+fn interact<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    options: &InterpreterOptions,
+    escape: ControlCode,
+) -> Result<()> {
+    use std::io::IsTerminal;
+    let mut stdin = std::io::stdin();
+    if options.cinema.is_some() || !stdin.is_terminal() {
+        tracing::debug!("interact skipped");
+        return Ok(());
     }
+    tracing::info!(escape = ?escape, "interact");
+    p.interact(&mut stdin, &mut std::io::stdout(), escape)?;
+    Ok(())
+}
 
-    pty.send("\n")?;
-    pty.flush()?;
+#[cfg(windows)]
+fn interact<O: LogWriter, P: Program>(
+    _p: &mut ReplSession<O, P>,
+    _options: &InterpreterOptions,
+    _escape: ControlCode,
+) -> Result<()> {
+    Ok(())
+}
 
+/// Send a signal to the program.
+///
+/// Names are case-insensitive and the `SIG` prefix is optional.
+#[cfg(unix)]
+fn send_signal<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    name: &str,
+) -> Result<()> {
+    use anticipate::Signal;
+    let upper = name.to_ascii_uppercase();
+    let signal = match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "HUP" => Signal::SIGHUP,
+        "INT" => Signal::SIGINT,
+        "QUIT" => Signal::SIGQUIT,
+        "KILL" => Signal::SIGKILL,
+        "USR1" => Signal::SIGUSR1,
+        "USR2" => Signal::SIGUSR2,
+        "TERM" => Signal::SIGTERM,
+        "CONT" => Signal::SIGCONT,
+        "STOP" => Signal::SIGSTOP,
+        "TSTP" => Signal::SIGTSTP,
+        "WINCH" => Signal::SIGWINCH,
+        _ => return Err(Error::InvalidSignal(name.to_owned())),
+    };
+    tracing::debug!(signal = %signal, "signal");
+    p.get_process_mut().send_signal(signal)
+}
+
+/// Signals are not supported on windows.
+#[cfg(windows)]
+fn send_signal<O: LogWriter, P: Program>(
+    _p: &mut ReplSession<O, P>,
+    name: &str,
+) -> Result<()> {
+    tracing::warn!(signal = %name, "signal ignored on windows");
     Ok(())
 }
 
-fn exec<O: LogWriter>(
-    p: &mut ReplSession<O>,
+/// Determine if the source of an instruction contains text.
+///
+/// The source is the instruction name followed by the argument.
+fn source_contains(cmd: &Instruction<'_>, text: &str) -> bool {
+    match cmd.argument() {
+        Some(argument) => format!("{} {}", cmd.name(), argument),
+        None => cmd.name().to_owned(),
+    }
+    .contains(text)
+}
+
+/// Resolve a screenshot path relative to the script.
+///
+/// The file may not exist yet so only the directory is resolved.
+#[cfg(feature = "screenshot")]
+fn screenshot_path(script: &Path, path: &str) -> Result<PathBuf> {
+    let path = Path::new(path);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::BadArguments(path.to_string_lossy().into()))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy(),
+        _ => ".".into(),
+    };
+    let dir = resolve_path(script, &dir)?;
+    Ok(Path::new(dir.as_ref()).join(file_name))
+}
+
+/// Determine if instructions contain a screenshot.
+fn has_screenshot(instructions: &[Instruction<'_>]) -> bool {
+    instructions.iter().any(|instruction| match instruction {
+        Instruction::Screenshot(_) => true,
+        Instruction::Include(source) => {
+            has_screenshot(source.borrow_instructions())
+        }
+        _ => false,
+    })
+}
+
+/// Wait for the prompt.
+///
+/// A timeout at the continuation prompt is reported as an
+/// unexpected continuation.
+fn wait_prompt<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    options: &InterpreterOptions,
+) -> Result<Captures> {
+    match p.expect_prompt() {
+        Err(anticipate::Error::ExpectTimeout(..))
+            if options.continuation_prompt.as_ref().is_some_and(|cont| {
+                p.is_at_continuation(cont).unwrap_or(false)
+            }) =>
+        {
+            let cont =
+                options.continuation_prompt.clone().unwrap_or_default();
+            Err(Error::UnexpectedContinuation(cont))
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Log the output matched by an expect instruction.
+fn log_captures(found: &Captures) {
+    tracing::debug!(matched = %found.matched_str(), "expect");
+    tracing::trace!(before = %found.before_str(), "expect");
+}
+
+/// Needle for branch arms that matches the pattern found
+/// earliest in the output.
+///
+/// Unlike [Any] which uses the first pattern in declaration
+/// order, the index of the matched pattern is available from
+/// [Captures::matched_index].
+#[derive(Debug)]
+struct Earliest<'a>(&'a [&'a str]);
+
+impl Needle for Earliest<'_> {
+    fn check(
+        &self,
+        buf: &[u8],
+        eof: bool,
+    ) -> std::result::Result<Vec<Match>, anticipate::Error> {
+        let mut earliest: Option<(usize, Vec<Match>)> = None;
+        for (index, pattern) in self.0.iter().enumerate() {
+            let found = pattern.check(buf, eof)?;
+            let Some(start) = found.iter().map(Match::start).min() else {
+                continue;
+            };
+            if earliest.as_ref().is_none_or(|(first, _)| start < *first) {
+                let found = found
+                    .into_iter()
+                    .map(|m| m.with_alternative(index))
+                    .collect();
+                earliest = Some((start, found));
+            }
+        }
+        Ok(earliest.map(|(_, found)| found).unwrap_or_default())
+    }
+}
+
+/// Poll the branch arms until a pattern matches.
+///
+/// When several arms match the arm found earliest in the
+/// output is used.
+///
+/// Returns the line for the matched arm or `None` when the
+/// program has exited.
+fn check_branch<'a, O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
+    arms: &[(&str, &'a str)],
+    options: &InterpreterOptions,
+    state: &ExecState,
+) -> Result<Option<&'a str>> {
+    let timeout = expect_timeout(options);
+    let patterns: Vec<_> = arms.iter().map(|(pattern, _)| *pattern).collect();
+    let needle = Earliest(&patterns);
+    let start = Instant::now();
+    loop {
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        state.check_total_timeout()?;
+
+        match p.check(&needle) {
+            Ok(found) if !found.is_empty() => {
+                log_captures(&found);
+                let index = found.matched_index().unwrap_or_default();
+                return Ok(Some(arms[index].1));
+            }
+            Ok(_) => {}
+            Err(anticipate::Error::Eof) => {
+                tracing::debug!("branch eof");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        if let Some(timeout) = timeout {
+            if start.elapsed() > timeout {
+                return Err(anticipate::Error::ExpectTimeout(
+                    timeout,
+                    format!("{:?}", patterns),
+                )
+                .into());
+            }
+        }
+
+        sleep(Duration::from_millis(10));
+    }
+}
+
+/// Sleep in small increments so cancellation is observed.
+fn sleep_cancellable(
+    delay: Duration,
+    options: &InterpreterOptions,
+) -> Result<()> {
+    let start = Instant::now();
+    while start.elapsed() < delay {
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        sleep(
+            delay
+                .saturating_sub(start.elapsed())
+                .min(Duration::from_millis(50)),
+        );
+    }
+    Ok(())
+}
+
+fn exec<O: LogWriter, P: Program>(
+    p: &mut ReplSession<O, P>,
     instructions: &[Instruction<'_>],
     options: &InterpreterOptions,
     pragma: Option<&str>,
+    state: &mut ExecState,
 ) -> Result<()> {
     for cmd in instructions.iter() {
+        if state.stopped {
+            return Ok(());
+        }
+        if options.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        state.check_total_timeout()?;
+
         tracing::debug!(instruction = ?cmd);
+        let skipped = match cmd {
+            Instruction::Include(_) => true,
+            Instruction::Comment(_) => !options.print_comments,
+            _ => false,
+        };
+        if options.cinema.is_some() && !is_silent(cmd, options) {
+            reveal(p, state)?;
+        }
+        if !skipped {
+            state.count += 1;
+            if let Some(on_instruction) = state.on_instruction.as_mut() {
+                on_instruction(state.count, cmd);
+            }
+        }
         match cmd {
             Instruction::Pragma(_) => {
                 if let (Some(cinema), Some(cmd)) = (&options.cinema, &pragma)
                 {
-                    if cinema.type_pragma {
-                        type_text(p, cmd, cinema)?;
+                    // The recorded shell is never replaced by the
+                    // pragma so always tell the user what happened
+                    if !cinema.run_pragma {
+                        tracing::warn!(
+                            pragma = %cmd,
+                            "pragma ignored when recording",
+                        );
+                    } else if cinema.type_pragma {
+                        tracing::warn!(
+                            pragma = %cmd,
+                            "pragma typed into the recorded shell",
+                        );
+                        type_text(p, cmd, cinema, options, &mut state.rng)?;
                     } else {
+                        tracing::warn!(
+                            pragma = %cmd,
+                            "pragma sent to the recorded shell",
+                        );
                         p.send_line(cmd)?;
                     }
                 }
             }
-            Instruction::Sleep(delay) => {
-                sleep(Duration::from_millis(*delay));
+            // Setup commands run before the script starts
+            Instruction::Exec(_) => {}
+            Instruction::Sleep { base, jitter } => {
+                let delay = sleep_jitter(*base, *jitter, &mut state.rng);
+                let delay = state.remaining(Duration::from_millis(delay));
+                sleep_cancellable(delay, options)?;
+                state.check_total_timeout()?;
+            }
+            Instruction::Section(name) => {
+                // Exit the previous section so sections are siblings
+                state.section.take();
+                tracing::info!(name = %name, "section");
+                let span = span!(Level::INFO, "section", name = %name);
+                state.section = Some(span.entered());
+                let time = state
+                    .started
+                    .map(|started| started.elapsed().as_secs_f64())
+                    .unwrap_or_default();
+                state.markers.push(Marker {
+                    time,
+                    label: name.to_owned(),
+                });
+            }
+            Instruction::Quiet(idle) => {
+                let idle = Duration::from_millis(*idle);
+                let found = p.expect_quiet_with(idle, || {
+                    if options.is_cancelled() {
+                        return Err(Error::Cancelled);
+                    }
+                    state.check_total_timeout()
+                })?;
+                log_captures(&found);
             }
             Instruction::Send(line) => {
+                options.check_line_length(line)?;
                 p.send(line)?;
             }
+            Instruction::SendBytes(bytes) => {
+                p.send(bytes)?;
+            }
             Instruction::Comment(line) | Instruction::SendLine(line) => {
                 if let (false, Instruction::Comment(_)) =
                     (options.print_comments, cmd)
@@ -421,40 +1974,257 @@ fn exec<O: LogWriter>(
                     continue;
                 }
 
-                let line = ScriptParser::interpolate(line)?;
-                if let Some(cinema) = &options.cinema {
-                    type_text(p, line.as_ref(), cinema)?;
+                let line = ScriptParser::interpolate(line, &state.variables)?;
+                options.check_line_length(&line)?;
+                if let (CommentStyle::Annotate, Instruction::Comment(_)) =
+                    (options.comment_style, cmd)
+                {
+                    if let Some(cinema) = &options.cinema {
+                        type_chars(
+                            p,
+                            line.as_ref(),
+                            cinema,
+                            options,
+                            &mut state.rng,
+                        )?;
+                    } else {
+                        p.send(line.as_ref())?;
+                    }
+                    // Clear the line so the comment is not executed
+                    p.send(ControlCode::NAK)?;
+                } else if let Some(cinema) = &options.cinema {
+                    type_text(
+                        p,
+                        line.as_ref(),
+                        cinema,
+                        options,
+                        &mut state.rng,
+                    )?;
                 } else {
                     p.send_line(line.as_ref())?;
                 }
             }
+            Instruction::Cd(path) => {
+                let path = ScriptParser::interpolate(path, &state.variables)?;
+                let line = format!("cd {}", shell_quote(&path));
+                if let Some(cinema) = &options.cinema {
+                    type_text(p, &line, cinema, options, &mut state.rng)?;
+                } else {
+                    p.send_line(&line)?;
+                }
+            }
+            Instruction::Eval(command) => {
+                let command =
+                    ScriptParser::interpolate(command, &state.variables)?;
+                if options.cinema.is_some() {
+                    // Only commands before visible output can be
+                    // cut from the start of the recording
+                    if state.visible {
+                        return Err(Error::EvalVisible(command.into_owned()));
+                    }
+                    state.hidden = true;
+                }
+                p.send_line(command.as_ref())?;
+                let found = p.expect_prompt()?;
+                log_captures(&found);
+            }
+            Instruction::Require { program, version } => {
+                require(program, *version)?;
+            }
+            Instruction::Lazy | Instruction::Greedy => {
+                state.lazy = matches!(cmd, Instruction::Lazy);
+                p.set_expect_lazy(state.lazy);
+            }
+            Instruction::Branch(arms) => {
+                if let Some(line) = check_branch(p, arms, options, state)? {
+                    let line =
+                        ScriptParser::interpolate(line, &state.variables)?;
+                    if let Some(cinema) = &options.cinema {
+                        type_text(
+                            p,
+                            line.as_ref(),
+                            cinema,
+                            options,
+                            &mut state.rng,
+                        )?;
+                    } else {
+                        p.send_line(line.as_ref())?;
+                    }
+                }
+            }
             Instruction::SendControl(ctrl) => {
-                let ctrl = ControlCode::try_from(*ctrl).map_err(|_| {
-                    Error::InvalidControlCode(ctrl.to_string())
-                })?;
-                p.send(ctrl)?;
+                p.send(parse_control_code(ctrl)?)?;
+            }
+            Instruction::Signal(name) => {
+                send_signal(p, name)?;
             }
             Instruction::Expect(line) => {
-                p.expect(line)?;
+                let line = line.as_ref();
+                let found = if options.normalize_newlines {
+                    p.expect(NormalizeNewlines(line))?
+                } else {
+                    p.expect(line)?
+                };
+                log_captures(&found);
             }
             Instruction::Regex(line) => {
-                p.expect(Regex(line))?;
+                let found = p.expect(Regex(line))?;
+                log_captures(&found);
+            }
+            Instruction::ExpectSequence(patterns) => {
+                let needles: Vec<Box<dyn Needle>> = patterns
+                    .iter()
+                    .map(|pattern| -> Box<dyn Needle> {
+                        if options.normalize_newlines {
+                            Box::new(NormalizeNewlines(pattern.clone()))
+                        } else {
+                            Box::new(pattern.clone())
+                        }
+                    })
+                    .collect();
+                for found in p.expect_ordered(needles)? {
+                    log_captures(&found);
+                }
             }
             Instruction::ReadLine => {
                 let mut line = String::new();
                 p.read_line(&mut line)?;
             }
+            Instruction::ExpectCaptures(pattern, names) => {
+                let found = p.expect(Regex(pattern))?;
+                log_captures(&found);
+                for (index, name) in names.iter().enumerate() {
+                    let value = found
+                        .get_str(index + 1)
+                        .ok_or_else(|| {
+                            Error::CaptureGroup(name.to_string(), index + 1)
+                        })?
+                        .into_owned();
+                    tracing::debug!(name = %name, value = %value, "capture");
+                    state.variables.insert(name.to_string(), value);
+                }
+            }
+            Instruction::Capture { name, pattern } => {
+                let found = p.expect(Regex(pattern))?;
+                log_captures(&found);
+                let value = found
+                    .get_str(1)
+                    .ok_or_else(|| Error::CaptureGroup(name.to_string(), 1))?
+                    .into_owned();
+                tracing::debug!(name = %name, value = %value, "capture");
+                state.variables.insert(name.to_string(), value);
+            }
+            Instruction::Assert(text) => {
+                assert_variable(text, &state.variables)?;
+            }
+            Instruction::ReadLineInto(name) => {
+                let mut line = String::new();
+                p.read_line(&mut line)?;
+                let value = line.trim().to_owned();
+                tracing::debug!(name = %name, value = %value, "capture");
+                state.variables.insert(name.to_owned(), value);
+            }
             Instruction::Wait => {
-                p.expect_prompt()?;
+                let found = wait_prompt(p, options)?;
+                log_captures(&found);
+            }
+            Instruction::WaitAny => {
+                let mut prompts = vec![p.get_prompt().to_owned()];
+                prompts.extend(options.prompts.iter().cloned());
+                let found = if p.is_prompt_regex() {
+                    let needles: Vec<_> =
+                        prompts.iter().map(|s| Regex(s.as_str())).collect();
+                    p.expect(Any(needles))?
+                } else {
+                    p.expect(Any(prompts.as_slice()))?
+                };
+                let index = found.matched_index().unwrap_or_default();
+                tracing::debug!(prompt = %prompts[index], "wait any");
+                log_captures(&found);
+            }
+            Instruction::SetPrompt(prompt) => {
+                let prompt =
+                    ScriptParser::interpolate(prompt, &state.variables)?;
+                tracing::debug!(prompt = %prompt, "set prompt");
+                p.set_prompt(prompt.into_owned());
+            }
+            Instruction::WaitExit => {
+                let status = wait_exit(p)?;
+                tracing::debug!(status = %status, "exit");
+                state.exit_status = Some(status);
+            }
+            Instruction::ExpectExit(expected) => {
+                let status = match state.exit_status {
+                    Some(status) => status,
+                    None => {
+                        let status = wait_exit(p)?;
+                        state.exit_status = Some(status);
+                        status
+                    }
+                };
+                if status != *expected {
+                    return Err(Error::ExitStatus {
+                        expected: *expected,
+                        actual: status,
+                        quit: None,
+                    });
+                }
+            }
+            Instruction::Resize { cols, rows } => {
+                resize(p, *cols, *rows)?;
+                if let Some(screen) = &state.screen {
+                    screen.resize(*cols, *rows);
+                }
+            }
+            #[cfg(feature = "screenshot")]
+            Instruction::Screenshot(path) => {
+                if let Some(screen) = &state.screen {
+                    p.read_available()?;
+                    let path = screenshot_path(&state.script, path)?;
+                    tracing::debug!(path = ?path, "screenshot");
+                    std::fs::write(path, screen.contents())?;
+                }
+            }
+            #[cfg(not(feature = "screenshot"))]
+            Instruction::Screenshot(_) => {
+                return Err(Error::Unsupported("screenshot"));
+            }
+            Instruction::LineEnding(ending) => {
+                p.set_line_ending(*ending);
             }
             Instruction::Clear => {
                 p.send_line("clear")?;
             }
+            Instruction::Interact(key) => {
+                let escape = parse_control_code(key.unwrap_or("]"))?;
+                interact(p, options, escape)?;
+            }
             Instruction::Flush => {
                 p.flush()?;
             }
             Instruction::Include(source) => {
-                exec(p, source.borrow_instructions(), options, pragma)?;
+                let lazy = state.lazy;
+                exec(
+                    p,
+                    source.borrow_instructions(),
+                    options,
+                    pragma,
+                    state,
+                )?;
+                // Expect algorithm is scoped to the include
+                state.lazy = lazy;
+                p.set_expect_lazy(lazy);
+            }
+            Instruction::Quit(command) => {
+                state.quit = Some(command.to_string());
+            }
+        }
+
+        if let (false, Some(text)) = (skipped, &options.stop_after) {
+            if source_contains(cmd, text) {
+                tracing::info!(instruction = %cmd.name(), "stop");
+                state.stopped = true;
+                return Ok(());
             }
         }
 
@@ -462,3 +2232,150 @@ fn exec<O: LogWriter>(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        assert_variable, shell_prompt, type_chars, typing_delay,
+        CinemaOptions, Earliest, InterpreterOptions, PromptPattern, PROMPT,
+    };
+    use crate::{Error, ScriptParser};
+    use anticipate::{Match, Needle};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::{collections::HashMap, io::Write};
+
+    #[derive(Default)]
+    struct CountingWriter {
+        written: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn shell_prompt_fallback() {
+        let literal = PromptPattern::Literal("$ ".to_owned());
+        assert_eq!("$ ", shell_prompt(&literal));
+        let regex = PromptPattern::Regex(r"\[[0-9]+\]> ".to_owned());
+        assert_eq!(PROMPT, shell_prompt(&regex));
+    }
+
+    #[test]
+    fn assert_variable_whitespace() {
+        let variables =
+            HashMap::from([("name".to_owned(), "value".to_owned())]);
+        assert!(assert_variable("$name == value", &variables).is_ok());
+        assert!(assert_variable("$name  ==\tvalue ", &variables).is_ok());
+        assert!(assert_variable("$name   =~  ^val", &variables).is_ok());
+        assert!(matches!(
+            assert_variable("$name  ==  other", &variables),
+            Err(Error::AssertionFailed { .. })
+        ));
+        assert!(matches!(
+            assert_variable("$name ==", &variables),
+            Err(Error::InvalidAssertion(..))
+        ));
+    }
+
+    #[test]
+    fn earliest_branch_arm() {
+        let arms = ["rty", "wer", "we"];
+        assert_eq!(
+            Earliest(&arms).check(b"qwerty", false).unwrap(),
+            vec![Match::new(1, 4).with_alternative(1)]
+        );
+        assert!(Earliest(&arms).check(b"abc", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn type_chars_flush() {
+        let options = InterpreterOptions::default();
+        let flushes = |flush_per_keystroke| {
+            let cinema = CinemaOptions {
+                delay: 10,
+                deviation: 1.0,
+                flush_per_keystroke,
+                ..Default::default()
+            };
+            let mut writer = CountingWriter::default();
+            type_chars(&mut writer, "héllo", &cinema, &options, &mut None)
+                .unwrap();
+            assert_eq!("héllo".as_bytes(), writer.written);
+            writer.flushes
+        };
+        assert_eq!(5, flushes(true));
+        assert_eq!(1, flushes(false));
+    }
+
+    #[test]
+    fn max_line_length() {
+        let options = InterpreterOptions::builder()
+            .max_line_length(64 * 1024)
+            .build();
+        let mut variables = HashMap::new();
+        variables.insert("LONG".to_owned(), "x".repeat(1024 * 1024));
+        let line =
+            ScriptParser::interpolate("echo $LONG", &variables).unwrap();
+        let result = options.check_line_length(&line);
+        assert!(matches!(
+            result,
+            Err(Error::LineTooLong(len, max))
+                if len == 1024 * 1024 + 5 && max == 64 * 1024
+        ));
+        assert!(options.check_line_length("echo short").is_ok());
+    }
+
+    #[test]
+    fn cinema_size_lookup() {
+        let lookup = |name: &str| match name {
+            "COLUMNS" => Some("120".to_owned()),
+            "LINES" => Some(" 36 ".to_owned()),
+            _ => None,
+        };
+        assert_eq!((120, 36), CinemaOptions::default().size_with(lookup));
+
+        let cinema = CinemaOptions {
+            cols: Some(100),
+            ..Default::default()
+        };
+        assert_eq!((100, 36), cinema.size_with(lookup));
+
+        let invalid = |_: &str| Some("0".to_owned());
+        assert_eq!((80, 24), CinemaOptions::default().size_with(invalid));
+        assert_eq!((80, 24), CinemaOptions::default().size_with(|_| None));
+    }
+
+    #[test]
+    fn seeded_typing_delay() {
+        let cinema = CinemaOptions::default();
+        let delays = |seed| {
+            let mut rng = Some(StdRng::seed_from_u64(seed));
+            (0..16)
+                .map(|_| typing_delay(&cinema, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(7), delays(7));
+        assert_ne!(delays(7), delays(8));
+    }
+
+    #[cfg(feature = "screenshot")]
+    #[test]
+    fn screenshot_path_relative_to_script() {
+        let src = std::path::Path::new("src").canonicalize().unwrap();
+        let script = std::path::Path::new("src/lib.rs");
+        let path = super::screenshot_path(script, "screen.txt").unwrap();
+        assert_eq!(src.join("screen.txt"), path);
+        let path = super::screenshot_path(script, "../screen.txt").unwrap();
+        assert_eq!(src.parent().unwrap().join("screen.txt"), path);
+    }
+}
@@ -2,13 +2,34 @@
 #![deny(missing_docs)]
 #![forbid(unsafe_code)]
 
+mod builder;
 mod error;
+mod filter;
+#[cfg(feature = "async")]
+mod future;
+mod golden;
+mod include;
 mod interpreter;
 mod parser;
+mod recording;
+mod screen;
 
-pub use error::Error;
-pub use interpreter::{CinemaOptions, InterpreterOptions, ScriptFile};
+pub use builder::InterpreterOptionsBuilder;
+pub use error::{Error, Location};
+#[cfg(feature = "async")]
+pub use future::RunFuture;
+pub use golden::{Golden, Transcript};
+pub use interpreter::{
+    CinemaOptions, CommentStyle, InstructionHook, InterpreterOptions,
+    PromptPattern, ScriptFile, SharedSession,
+};
 pub use parser::*;
+pub use recording::{render_cast, RecordingResult, RenderFormat};
+
+pub use anticipate::checksum::Checksum;
+pub use anticipate::log::LogWriter;
+pub use anticipate::report::{RunReport, TestCase};
+pub use anticipate::LineEnding;
 
 /// Result type for the parser.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,15 +1,28 @@
 use crate::{
-    error::LexError, interpreter::ScriptSource, resolve_path, Error, Result,
+    error::{LexError, Location},
+    include,
+    interpreter::ScriptSource,
+    Error, Result,
 };
+use anticipate::LineEnding;
 use logos::{Lexer, Logos};
 use std::{
     borrow::Cow,
+    collections::HashMap,
     ops::Range,
     path::{Path, PathBuf},
 };
 
+/// Error for the text at the current position of a lexer.
+fn lex_error<'s, T>(lex: &Lexer<'s, T>) -> LexError
+where
+    T: Logos<'s, Source = str>,
+{
+    LexError::UnexpectedToken(lex.span().start, lex.slice().to_owned())
+}
+
 fn pragma(lex: &mut Lexer<Token>) -> Option<String> {
-    let slice = lex.slice();
+    let slice = lex.slice().trim_start();
     let value = &slice[2..];
     Some(value.to_owned())
 }
@@ -23,35 +36,149 @@ fn integer(lex: &mut Lexer<Token>) -> Option<u64> {
     }
 }
 
+/// Sleep duration with optional jitter after `±` or `+-`.
+fn sleep(lex: &mut Lexer<Token>) -> Option<(u64, u64)> {
+    let slice = lex.slice();
+    let (_, rest) = slice.split_once("sleep")?;
+    match rest.split_once('±').or_else(|| rest.split_once("+-")) {
+        Some((base, jitter)) => {
+            Some((duration(base.trim())?, duration(jitter.trim())?))
+        }
+        None => Some((duration(rest.trim())?, 0)),
+    }
+}
+
+/// Duration in milliseconds with an optional
+/// `ms`, `s` or `m` suffix.
+fn duration(value: &str) -> Option<u64> {
+    let digits = value.find(|c: char| !c.is_ascii_digit());
+    let (num, unit) = value.split_at(digits.unwrap_or(value.len()));
+    let num: u64 = num.parse().ok()?;
+    match unit {
+        "" | "ms" => Some(num),
+        "s" => num.checked_mul(1000),
+        "m" => num.checked_mul(60_000),
+        _ => None,
+    }
+}
+
+fn exit_code(lex: &mut Lexer<Token>) -> Option<i32> {
+    integer(lex).and_then(|num| i32::try_from(num).ok())
+}
+
+fn dimensions(lex: &mut Lexer<Token>) -> Option<(u16, u16)> {
+    let mut parts = lex.slice().split_whitespace().skip(2);
+    let cols = parts.next()?.parse().ok()?;
+    let rows = parts.next()?.parse().ok()?;
+    Some((cols, rows))
+}
+
+fn identifier(lex: &mut Lexer<Token>) -> Option<String> {
+    let slice = lex.slice();
+    slice.split_whitespace().last().map(|s| s.to_owned())
+}
+
+/// Determine if text is a valid variable name.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|c| c.is_ascii_alphabetic() || c == '_')
+        .unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 #[derive(Logos, Debug, PartialEq, Clone)]
 #[logos(error = LexError)]
 enum Token {
-    #[regex("#![^\n]+", callback = pragma)]
+    #[regex("[\\t ]*#![^\n]+", callback = pragma)]
     Pragma(String),
-    #[regex("#[$]\\s+sendline\\s")]
+    #[regex("[\\t ]*#[$]\\s+sendline\\s")]
     SendLine,
-    #[regex("#[$]\\s+sendcontrol\\s")]
+    #[regex("[\\t ]*#[$]\\s+sendcontrol\\s")]
     SendControl,
-    #[regex("#[$]\\s+expect\\s")]
+    #[regex("[\\t ]*#[$]\\s+sendbytes\\s")]
+    SendBytes,
+    #[regex("[\\t ]*#[$]\\s+signal\\s")]
+    Signal,
+    #[regex("[\\t ]*#[$]\\s+expect\\s")]
     Expect,
-    #[regex("#[$]\\s+regex\\s")]
+    #[regex("[\\t ]*#[$]\\s+regex\\s")]
     Regex,
-    #[regex("#[$]\\s+sleep\\s+([0-9]+)", callback = integer)]
-    Sleep(u64),
-    #[regex("#[$]\\s+readline\\s*")]
+    #[regex("[\\t ]*#[$]\\s+expect-captures\\s")]
+    ExpectCaptures,
+    #[regex("[\\t ]*#[$]\\s+capture\\s")]
+    Capture,
+    #[regex("[\\t ]*#[$]\\s+expectseq\\s")]
+    ExpectSequence,
+    #[regex(
+        "[\\t ]*#[$]\\s+sleep\\s+[0-9a-zA-Z]+([\\t ]*(±|\\+-)[\\t ]*[0-9a-zA-Z]+)?",
+        callback = sleep
+    )]
+    Sleep((u64, u64)),
+    #[regex("[\\t ]*#[$]\\s+quiet\\s+([0-9]+)", callback = integer)]
+    Quiet(u64),
+    #[regex("[\\t ]*#[$]\\s+readline\\s*")]
     ReadLine,
-    #[regex("#[$]\\s+wait\\s*")]
+    #[regex(
+        "[\\t ]*#[$]\\s+readline[\\t ]+[a-zA-Z_][a-zA-Z0-9_]*",
+        callback = identifier
+    )]
+    ReadLineInto(String),
+    #[regex("[\\t ]*#[$]\\s+wait\\s*")]
+    #[regex("[\\t ]*#[$]\\s+expectprompt\\s*")]
     Wait,
-    #[regex("#[$]\\s+clear\\s*")]
+    #[regex("[\\t ]*#[$]\\s+setprompt\\s")]
+    SetPrompt,
+    #[regex("[\\t ]*#[$]\\s+waitexit\\s*")]
+    WaitExit,
+    #[regex("[\\t ]*#[$]\\s+waitany\\s*")]
+    WaitAny,
+    #[regex("[\\t ]*#[$]\\s+expectexit\\s+([0-9]+)", callback = exit_code)]
+    ExpectExit(i32),
+    #[regex("[\\t ]*#[$]\\s+clear\\s*")]
     Clear,
-    #[regex("#[$]\\s+send ")]
+    #[regex("[\\t ]*#[$]\\s+interact[\\t ]*")]
+    Interact,
+    #[regex("[\\t ]*#[$]\\s+screenshot\\s")]
+    Screenshot,
+    #[regex(
+        "[\\t ]*#[$]\\s+resize[\\t ]+[0-9]+[\\t ]+[0-9]+",
+        callback = dimensions
+    )]
+    Resize((u16, u16)),
+    #[regex("[\\t ]*#[$]\\s+lineending\\s")]
+    LineEnding,
+    #[regex("[\\t ]*#[$]\\s+send ")]
     Send,
-    #[regex("#[$]\\s+flush\\s*")]
+    #[regex("[\\t ]*#[$]\\s+flush\\s*")]
     Flush,
-    #[regex("#[$]\\s+include\\s+")]
+    #[regex("[\\t ]*#[$]\\s+include\\s+")]
     Include,
-    #[regex("#[$].?", priority = 4)]
+    #[regex("[\\t ]*#[$]\\s+quit\\s")]
+    Quit,
+    #[regex("[\\t ]*#[$]\\s+cd\\s")]
+    Cd,
+    #[regex("[\\t ]*#[$]\\s+eval\\s")]
+    Eval,
+    #[regex("[\\t ]*#[$]\\s+exec\\s")]
+    Exec,
+    #[regex("[\\t ]*#[$]\\s+require\\s")]
+    Require,
+    #[regex("[\\t ]*#[$]\\s+assert\\s")]
+    Assert,
+    #[regex("[\\t ]*#[$]\\s+branch\\s*")]
+    Branch,
+    #[regex("[\\t ]*#[$]\\s+lazy\\s*")]
+    Lazy,
+    #[regex("[\\t ]*#[$]\\s+greedy\\s*")]
+    Greedy,
+    #[regex("[\\t ]*\\\\#[$]", priority = 5)]
+    Escaped,
+    #[regex("[\\t ]*#[$].?", priority = 4)]
     Command,
+    #[regex("[\\t ]*#@[\\t ]+section[\\t ]+")]
+    Section,
     #[regex("\r?\n", priority = 3)]
     Newline,
     #[regex("(\t| )*#[^$!]?#*.", priority = 2)]
@@ -65,10 +192,25 @@ enum Token {
 enum EnvVars {
     #[regex("[$][a-zA-Z0-9_]+")]
     Var,
+    #[regex("[$][{][a-zA-Z0-9_]+[}]")]
+    Braced,
+    #[regex("[$][{][a-zA-Z0-9_]+:-[^}]*[}]")]
+    BracedDefault,
     #[regex(".", priority = 0)]
     Text,
 }
 
+/// Include declared in the source before the files are resolved.
+#[derive(Debug)]
+pub struct IncludeRequest<'s> {
+    /// Path or pattern as written in the source.
+    pub text: &'s str,
+    /// Index in the parent instructions.
+    pub index: usize,
+    /// Location of the include in the source.
+    pub location: Location,
+}
+
 /// Include reference.
 #[derive(Debug)]
 pub struct Include {
@@ -83,155 +225,635 @@ pub struct Include {
 pub enum Instruction<'s> {
     /// Program to execute.
     Pragma(String),
+    /// Setup command run to completion before the program starts.
+    Exec(&'s str),
     /// Send a line of text.
     SendLine(&'s str),
     /// Send a control character.
     SendControl(&'s str),
+    /// Send a signal to the program (unix only).
+    Signal(String),
     /// Expect a string.
-    Expect(&'s str),
+    ///
+    /// Quoted arguments have the quotes removed and
+    /// escape sequences processed.
+    Expect(Cow<'s, str>),
     /// Expect a regex match.
     Regex(&'s str),
-    /// Sleep a while.
-    Sleep(u64),
+    /// Expect a regex match and bind groups to variables.
+    ExpectCaptures(&'s str, Vec<&'s str>),
+    /// Expect a regex match and bind the first group to a variable.
+    Capture {
+        /// Variable name.
+        name: &'s str,
+        /// Regular expression with a capture group.
+        pattern: &'s str,
+    },
+    /// Expect each string in order.
+    ExpectSequence(Vec<String>),
+    /// Sleep a while in milliseconds.
+    Sleep {
+        /// Delay in milliseconds.
+        base: u64,
+        /// Maximum random change to the delay in milliseconds.
+        jitter: u64,
+    },
+    /// Wait until there is no output for a number of milliseconds.
+    Quiet(u64),
     /// Comment text.
     Comment(&'s str),
+    /// Start a named section.
+    Section(String),
     /// Read a line of output.
     ReadLine,
+    /// Read a line of output into a variable.
+    ReadLineInto(String),
     /// Wait for the prompt.
     Wait,
+    /// Change the prompt pattern.
+    SetPrompt(&'s str),
+    /// Wait for the program to exit.
+    WaitExit,
+    /// Wait for the prompt or one of the additional prompts.
+    WaitAny,
+    /// Expect the program exit status.
+    ExpectExit(i32),
     /// Clear the screen.
     Clear,
+    /// Hand control to the user until the escape key is pressed.
+    Interact(Option<&'s str>),
+    /// Write the visible terminal text to a file.
+    Screenshot(&'s str),
+    /// Resize the terminal.
+    Resize {
+        /// Number of columns.
+        cols: u16,
+        /// Number of rows.
+        rows: u16,
+    },
+    /// Set the line ending for sent lines.
+    LineEnding(LineEnding),
     /// Send text, the output stream is not flushed.
     Send(&'s str),
+    /// Send raw bytes decoded from hex, the output stream is not flushed.
+    SendBytes(Vec<u8>),
     /// Flush the output stream.
     Flush,
     /// Include script.
     Include(ScriptSource),
+    /// Command sent to quit the program.
+    Quit(&'s str),
+    /// Change the working directory of the shell.
+    Cd(&'s str),
+    /// Run a shell command without typing it in a recording.
+    Eval(&'s str),
+    /// Check a program is installed with a minimum version.
+    Require {
+        /// Program to run with `--version`.
+        program: &'s str,
+        /// Minimum version of the program.
+        version: Option<&'s str>,
+    },
+    /// Assert a variable matches a value.
+    Assert(&'s str),
+    /// Send the line for the first pattern that matches.
+    Branch(Vec<(&'s str, &'s str)>),
+    /// Use the lazy expect algorithm.
+    Lazy,
+    /// Use the greedy expect algorithm.
+    Greedy,
+}
+
+impl Instruction<'_> {
+    /// Name of the instruction.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pragma(_) => "pragma",
+            Self::Exec(_) => "exec",
+            Self::SendLine(_) => "sendline",
+            Self::SendControl(_) => "sendcontrol",
+            Self::Signal(_) => "signal",
+            Self::Expect(_) => "expect",
+            Self::Regex(_) => "regex",
+            Self::ExpectCaptures(_, _) => "expect-captures",
+            Self::Capture { .. } => "capture",
+            Self::ExpectSequence(_) => "expectseq",
+            Self::Sleep { .. } => "sleep",
+            Self::Quiet(_) => "quiet",
+            Self::Comment(_) => "comment",
+            Self::Section(_) => "section",
+            Self::ReadLine | Self::ReadLineInto(_) => "readline",
+            Self::Wait => "wait",
+            Self::SetPrompt(_) => "setprompt",
+            Self::WaitExit => "waitexit",
+            Self::WaitAny => "waitany",
+            Self::ExpectExit(_) => "expectexit",
+            Self::Clear => "clear",
+            Self::Interact(_) => "interact",
+            Self::Screenshot(_) => "screenshot",
+            Self::Resize { .. } => "resize",
+            Self::LineEnding(_) => "lineending",
+            Self::Send(_) => "send",
+            Self::SendBytes(_) => "sendbytes",
+            Self::Flush => "flush",
+            Self::Include(_) => "include",
+            Self::Quit(_) => "quit",
+            Self::Cd(_) => "cd",
+            Self::Eval(_) => "eval",
+            Self::Require { .. } => "require",
+            Self::Assert(_) => "assert",
+            Self::Branch(_) => "branch",
+            Self::Lazy => "lazy",
+            Self::Greedy => "greedy",
+        }
+    }
+
+    /// Argument for the instruction.
+    pub fn argument(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Self::Pragma(value)
+            | Self::ReadLineInto(value)
+            | Self::Signal(value)
+            | Self::Section(value) => Some(Cow::Borrowed(value)),
+            Self::Expect(value) => Some(Cow::Borrowed(value)),
+            Self::SendLine(value)
+            | Self::SendControl(value)
+            | Self::Regex(value)
+            | Self::Comment(value)
+            | Self::Send(value)
+            | Self::Quit(value)
+            | Self::Cd(value)
+            | Self::Eval(value)
+            | Self::Exec(value)
+            | Self::SetPrompt(value)
+            | Self::Screenshot(value)
+            | Self::Assert(value) => Some(Cow::Borrowed(value)),
+            Self::ExpectCaptures(pattern, names) => {
+                Some(Cow::Owned(format!("{} {}", pattern, names.join(" "))))
+            }
+            Self::Capture { name, pattern } => {
+                Some(Cow::Owned(format!("{} {}", name, pattern)))
+            }
+            Self::ExpectSequence(patterns) => {
+                Some(Cow::Owned(patterns.join(" ")))
+            }
+            Self::Require { program, version } => Some(match version {
+                Some(version) => {
+                    Cow::Owned(format!("{} >= {}", program, version))
+                }
+                None => Cow::Borrowed(program),
+            }),
+            Self::SendBytes(bytes) => Some(Cow::Owned(
+                bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+            )),
+            Self::Sleep { base, jitter: 0 } | Self::Quiet(base) => {
+                Some(Cow::Owned(base.to_string()))
+            }
+            Self::Sleep { base, jitter } => {
+                Some(Cow::Owned(format!("{} ±{}", base, jitter)))
+            }
+            Self::ExpectExit(code) => Some(Cow::Owned(code.to_string())),
+            Self::Resize { cols, rows } => {
+                Some(Cow::Owned(format!("{} {}", cols, rows)))
+            }
+            Self::LineEnding(ending) => Some(Cow::Borrowed(match ending {
+                LineEnding::Lf => "lf",
+                LineEnding::CrLf => "crlf",
+                LineEnding::Cr => "cr",
+            })),
+            Self::Interact(key) => key.map(Cow::Borrowed),
+            Self::Branch(arms) => {
+                let patterns: Vec<_> =
+                    arms.iter().map(|(pattern, _)| *pattern).collect();
+                Some(Cow::Owned(patterns.join(" | ")))
+            }
+            Self::ReadLine
+            | Self::Wait
+            | Self::WaitExit
+            | Self::WaitAny
+            | Self::Clear
+            | Self::Flush
+            | Self::Lazy
+            | Self::Greedy
+            | Self::Include(_) => None,
+        }
+    }
 }
 
 /// Sequence of commands to execute.
 pub type Instructions<'s> = Vec<Instruction<'s>>;
 
-/// Parser for scripts.
-#[derive(Debug)]
-pub struct ScriptParser;
+/// Iterator over instructions that lexes the source lazily.
+///
+/// Lexing never touches the filesystem, includes are collected
+/// and available from [ScriptIter::includes], they are resolved
+/// separately by [ScriptParser::parse_file].
+pub struct ScriptIter<'s> {
+    lex: Lexer<'s, Token>,
+    source: &'s str,
+    count: usize,
+    /// Whether an instruction other than the pragma, setup
+    /// commands and comments has been parsed.
+    started: bool,
+    includes: Vec<IncludeRequest<'s>>,
+    finished: bool,
+}
 
-impl ScriptParser {
-    /// Parse input commands.
-    pub fn parse(source: &str) -> Result<Instructions<'_>> {
-        let (instructions, _) = ScriptParser::parse_file(source, "")?;
-        Ok(instructions)
+impl<'s> ScriptIter<'s> {
+    fn new(source: &'s str) -> Self {
+        Self {
+            lex: Token::lexer(source),
+            source,
+            count: 0,
+            started: false,
+            includes: Vec::new(),
+            finished: false,
+        }
     }
 
-    /// Parse input commands relative to a file path.
-    pub fn parse_file(
-        source: &str,
-        base: impl AsRef<Path>,
-    ) -> Result<(Instructions<'_>, Vec<Include>)> {
-        let mut cmd = Vec::new();
-        let mut lex = Token::lexer(source);
-        let mut next_token = lex.next();
-        let mut includes = Vec::new();
-        while let Some(token) = next_token.take() {
-            let token = token?;
-            let span = lex.span();
+    /// Includes declared in the instructions lexed so far.
+    pub fn includes(&self) -> &[IncludeRequest<'s>] {
+        &self.includes
+    }
+
+    fn parse_text(
+        &mut self,
+        start: Option<Range<usize>>,
+    ) -> Result<(&'s str, Range<usize>)> {
+        ScriptParser::parse_text(&mut self.lex, self.source, start)
+    }
+
+    /// Parse branch arms until the end of the block.
+    ///
+    /// Each arm is a `#$ PATTERN => LINE` declaration and
+    /// the block is terminated by `#$ end`.
+    fn parse_branch(
+        &mut self,
+        start: usize,
+    ) -> Result<Vec<(&'s str, &'s str)>> {
+        let source = self.source;
+        let mut offset = self.lex.span().end;
+        let mut arms = Vec::new();
+        for line in self.lex.remainder().split_inclusive('\n') {
+            let span = offset..offset + line.trim_end().len();
+            offset += line.len();
+            let text = line.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            let arm = text.strip_prefix("#$").map(|arm| arm.trim());
+            if let Some("end") = arm {
+                self.lex.bump(offset - self.lex.span().end);
+                return Ok(arms);
+            }
+
+            match arm.and_then(|arm| arm.split_once("=>")) {
+                Some((pattern, line)) if !pattern.trim().is_empty() => {
+                    arms.push((pattern.trim(), line.trim()));
+                }
+                _ => {
+                    return Err(Error::InvalidBranch(
+                        text.to_owned(),
+                        Location::new(source, span),
+                    ));
+                }
+            }
+        }
+        Err(Error::UnterminatedBranch(Location::new(
+            source,
+            start..self.lex.span().end,
+        )))
+    }
+
+    fn next_instruction(&mut self) -> Result<Option<Instruction<'s>>> {
+        let source = self.source;
+        while let Some(token) = self.lex.next() {
+            let token = token.map_err(|_| lex_error(&self.lex))?;
+            let span = self.lex.span();
             tracing::debug!(token = ?token, "parse");
             match token {
                 Token::Command => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    return Err(Error::UnknownInstruction(text.to_owned()));
+                    let (text, finish) = self.parse_text(None)?;
+                    return Err(Error::UnknownInstruction(
+                        text.to_owned(),
+                        Location::new(source, span.start..finish.end),
+                    ));
+                }
+                // Escaped command is sent as a literal line
+                Token::Escaped => {
+                    let (_, finish) = self.parse_text(None)?;
+                    let text =
+                        &source[span.end - 2..finish.end.max(span.end)];
+                    return Ok(Some(Instruction::SendLine(text)));
+                }
+                Token::Section => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Section(
+                        text.trim().to_owned(),
+                    )));
                 }
                 Token::Comment => {
-                    let (_, finish) =
-                        Self::parse_text(&mut lex, source, None)?;
+                    let (_, finish) = self.parse_text(None)?;
                     let text = &source[span.start..finish.end];
-                    cmd.push(Instruction::Comment(text));
+                    return Ok(Some(Instruction::Comment(text)));
                 }
                 Token::Include => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    let text = text.trim();
-                    match resolve_path(base.as_ref(), text) {
-                        Ok(path) => {
-                            let path: PathBuf = path.as_ref().into();
-                            if !path.try_exists()? {
-                                return Err(Error::Include(
-                                    text.to_owned(),
-                                    path,
-                                ));
-                            }
-                            includes.push(Include {
-                                index: cmd.len(),
-                                path,
-                            });
-                        }
-                        Err(_) => {
-                            return Err(Error::Include(
-                                text.to_owned(),
-                                PathBuf::from(text),
-                            ));
-                        }
-                    }
+                    let (text, finish) = self.parse_text(None)?;
+                    self.includes.push(IncludeRequest {
+                        text: text.trim(),
+                        index: self.count,
+                        location: Location::new(
+                            source,
+                            span.start..finish.end,
+                        ),
+                    });
                 }
                 Token::ReadLine => {
-                    cmd.push(Instruction::ReadLine);
+                    return Ok(Some(Instruction::ReadLine));
+                }
+                Token::ReadLineInto(name) => {
+                    return Ok(Some(Instruction::ReadLineInto(name)));
                 }
                 Token::Wait => {
-                    cmd.push(Instruction::Wait);
+                    return Ok(Some(Instruction::Wait));
+                }
+                Token::WaitExit => {
+                    return Ok(Some(Instruction::WaitExit));
+                }
+                Token::WaitAny => {
+                    return Ok(Some(Instruction::WaitAny));
+                }
+                Token::SetPrompt => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::SetPrompt(text)));
+                }
+                Token::ExpectExit(code) => {
+                    return Ok(Some(Instruction::ExpectExit(code)));
+                }
+                Token::Resize((cols, rows)) => {
+                    return Ok(Some(Instruction::Resize { cols, rows }));
+                }
+                Token::LineEnding => {
+                    let (text, finish) = self.parse_text(None)?;
+                    let ending = match text.trim() {
+                        "lf" => LineEnding::Lf,
+                        "crlf" => LineEnding::CrLf,
+                        "cr" => LineEnding::Cr,
+                        _ => {
+                            return Err(Error::InvalidLineEnding(
+                                text.to_owned(),
+                                Location::new(source, span.start..finish.end),
+                            ))
+                        }
+                    };
+                    return Ok(Some(Instruction::LineEnding(ending)));
                 }
                 Token::Clear => {
-                    cmd.push(Instruction::Clear);
+                    return Ok(Some(Instruction::Clear));
+                }
+                Token::Lazy => {
+                    return Ok(Some(Instruction::Lazy));
+                }
+                Token::Greedy => {
+                    return Ok(Some(Instruction::Greedy));
                 }
                 Token::Pragma(pragma) => {
-                    if !cmd.is_empty() {
+                    if self.count > 0 {
                         return Err(Error::PragmaFirst);
                     }
-                    cmd.push(Instruction::Pragma(pragma));
+                    return Ok(Some(Instruction::Pragma(pragma)));
                 }
                 Token::Send => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    cmd.push(Instruction::Send(text));
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Send(text)));
                 }
                 Token::Flush => {
-                    cmd.push(Instruction::Flush);
+                    return Ok(Some(Instruction::Flush));
+                }
+                Token::Assert => {
+                    let (text, finish) = self.parse_text(None)?;
+                    if ScriptParser::parse_assertion(text).is_none() {
+                        return Err(Error::InvalidAssertion(
+                            text.trim().to_owned(),
+                            Location::new(source, span.start..finish.end),
+                        ));
+                    }
+                    return Ok(Some(Instruction::Assert(text)));
+                }
+                Token::Branch => {
+                    let arms = self.parse_branch(span.start)?;
+                    return Ok(Some(Instruction::Branch(arms)));
+                }
+                Token::Quit => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Quit(text)));
+                }
+                Token::Interact => {
+                    let (text, _) = self.parse_text(None)?;
+                    let key = Some(text.trim()).filter(|k| !k.is_empty());
+                    return Ok(Some(Instruction::Interact(key)));
+                }
+                Token::Screenshot => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Screenshot(text.trim())));
+                }
+                Token::Exec => {
+                    let (text, finish) = self.parse_text(None)?;
+                    if self.started {
+                        return Err(Error::ExecFirst(Location::new(
+                            source,
+                            span.start..finish.end,
+                        )));
+                    }
+                    return Ok(Some(Instruction::Exec(text.trim())));
+                }
+                Token::Eval => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Eval(text)));
+                }
+                Token::Cd => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Cd(text)));
+                }
+                Token::Require => {
+                    let (text, finish) = self.parse_text(None)?;
+                    let (program, version) = ScriptParser::parse_requirement(
+                        text,
+                    )
+                    .ok_or_else(|| {
+                        Error::InvalidRequirement(
+                            text.trim().to_owned(),
+                            Location::new(source, span.start..finish.end),
+                        )
+                    })?;
+                    return Ok(Some(Instruction::Require {
+                        program,
+                        version,
+                    }));
                 }
                 Token::SendLine => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    cmd.push(Instruction::SendLine(text));
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::SendLine(text)));
                 }
                 Token::Expect => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    cmd.push(Instruction::Expect(text));
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Expect(
+                        ScriptParser::unquote(text),
+                    )));
                 }
                 Token::Regex => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    cmd.push(Instruction::Regex(text));
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Regex(text)));
+                }
+                Token::ExpectCaptures => {
+                    let (text, finish) = self.parse_text(None)?;
+                    let (pattern, names) = ScriptParser::parse_captures(text)
+                        .ok_or_else(|| {
+                            Error::InvalidCaptures(
+                                text.trim().to_owned(),
+                                Location::new(source, span.start..finish.end),
+                            )
+                        })?;
+                    return Ok(Some(Instruction::ExpectCaptures(
+                        pattern, names,
+                    )));
+                }
+                Token::Capture => {
+                    let (text, finish) = self.parse_text(None)?;
+                    let (name, pattern) = ScriptParser::parse_capture(text)
+                        .ok_or_else(|| {
+                        Error::InvalidCapture(
+                            text.trim().to_owned(),
+                            Location::new(source, span.start..finish.end),
+                        )
+                    })?;
+                    return Ok(Some(Instruction::Capture { name, pattern }));
+                }
+                Token::ExpectSequence => {
+                    let (text, finish) = self.parse_text(None)?;
+                    let patterns = comma::parse_command(text)
+                        .filter(|patterns| !patterns.is_empty())
+                        .ok_or_else(|| {
+                            Error::InvalidSequence(
+                                text.trim().to_owned(),
+                                Location::new(source, span.start..finish.end),
+                            )
+                        })?;
+                    return Ok(Some(Instruction::ExpectSequence(patterns)));
                 }
                 Token::SendControl => {
-                    let (text, _) = Self::parse_text(&mut lex, source, None)?;
-                    cmd.push(Instruction::SendControl(text));
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::SendControl(text)));
+                }
+                Token::Signal => {
+                    let (text, _) = self.parse_text(None)?;
+                    return Ok(Some(Instruction::Signal(
+                        text.trim().to_owned(),
+                    )));
                 }
-                Token::Sleep(num) => {
-                    cmd.push(Instruction::Sleep(num));
+                Token::SendBytes => {
+                    let (text, finish) = self.parse_text(None)?;
+                    let bytes =
+                        ScriptParser::parse_hex(text).ok_or_else(|| {
+                            Error::InvalidHex(
+                                text.to_owned(),
+                                Location::new(source, span.start..finish.end),
+                            )
+                        })?;
+                    return Ok(Some(Instruction::SendBytes(bytes)));
+                }
+                Token::Sleep((base, jitter)) => {
+                    return Ok(Some(Instruction::Sleep { base, jitter }));
+                }
+                Token::Quiet(num) => {
+                    return Ok(Some(Instruction::Quiet(num)));
                 }
                 // Unhandled text is send line
                 Token::Text => {
-                    let (text, _) =
-                        Self::parse_text(&mut lex, source, Some(span))?;
+                    let (text, finish) =
+                        self.parse_text(Some(span.clone()))?;
                     if text.starts_with("#$") {
                         return Err(Error::UnknownInstruction(
                             text.to_owned(),
+                            Location::new(source, span.start..finish.end),
                         ));
                     }
-                    cmd.push(Instruction::SendLine(text));
+                    return Ok(Some(Instruction::SendLine(text)));
                 }
                 Token::Newline => {}
             }
-            next_token = lex.next();
         }
+        Ok(None)
+    }
+}
+
+impl<'s> Iterator for ScriptIter<'s> {
+    type Item = Result<Instruction<'s>>;
 
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_instruction() {
+            Ok(Some(instruction)) => {
+                self.count += 1;
+                self.started |= !matches!(
+                    instruction,
+                    Instruction::Pragma(_)
+                        | Instruction::Exec(_)
+                        | Instruction::Comment(_)
+                );
+                Some(Ok(instruction))
+            }
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Parser for scripts.
+#[derive(Debug)]
+pub struct ScriptParser;
+
+impl ScriptParser {
+    /// Parse input commands.
+    pub fn parse(source: &str) -> Result<Instructions<'_>> {
+        let (instructions, _) = ScriptParser::parse_file(source, "", &[])?;
+        Ok(instructions)
+    }
+
+    /// Parse input commands relative to a file path.
+    ///
+    /// Includes not found relative to the file are searched
+    /// for in the include roots in order.
+    pub fn parse_file<'s>(
+        source: &'s str,
+        base: impl AsRef<Path>,
+        include_roots: &[PathBuf],
+    ) -> Result<(Instructions<'s>, Vec<Include>)> {
+        let mut iter = ScriptIter::new(source);
+        let cmd = iter.by_ref().collect::<Result<Instructions<'_>>>()?;
+        let includes =
+            include::resolve(iter.includes, base.as_ref(), include_roots)?;
         Ok((cmd, includes))
     }
 
+    /// Iterate instructions lexing the source lazily.
+    ///
+    /// Iteration stops after the first error. The filesystem
+    /// is not accessed so includes are not yielded, they are
+    /// available from [ScriptIter::includes].
+    pub fn iter(source: &str) -> ScriptIter<'_> {
+        ScriptIter::new(source)
+    }
+
     fn parse_text<'s>(
         lex: &mut Lexer<Token>,
         source: &'s str,
@@ -246,9 +868,9 @@ impl ScriptParser {
         let mut finish: Range<usize> = lex.span();
         let mut next_token = lex.next();
         while let Some(token) = next_token.take() {
-            let token = token?;
+            let token = token.map_err(|_| lex_error(lex))?;
             match token {
-                Token::Text => {
+                Token::Text | Token::Escaped => {
                     finish = lex.span();
                 }
                 _ => break,
@@ -258,20 +880,168 @@ impl ScriptParser {
         Ok((&source[begin.start..finish.end], finish))
     }
 
-    pub(crate) fn interpolate(value: &str) -> Result<Cow<str>> {
+    /// Decode pairs of hex digits into bytes.
+    ///
+    /// Whitespace between the digits is ignored.
+    fn parse_hex(text: &str) -> Option<Vec<u8>> {
+        let digits: Vec<u8> =
+            text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        if digits.is_empty() || digits.len() % 2 == 1 {
+            return None;
+        }
+        digits
+            .chunks(2)
+            .map(|pair| {
+                if !pair.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+                let pair = std::str::from_utf8(pair).ok()?;
+                u8::from_str_radix(pair, 16).ok()
+            })
+            .collect()
+    }
+
+    /// Parse a program with an optional minimum version.
+    ///
+    /// The version must be dot separated numbers, for example
+    /// `git >= 2.30`.
+    fn parse_requirement(text: &str) -> Option<(&str, Option<&str>)> {
+        let mut parts = text.split_whitespace();
+        let program = parts.next()?;
+        match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => Some((program, None)),
+            (Some(">="), Some(version), None)
+                if version.split('.').all(|n| n.parse::<u64>().is_ok()) =>
+            {
+                Some((program, Some(version)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse a pattern followed by variable names.
+    ///
+    /// The pattern may be wrapped in double quotes
+    /// when it contains whitespace.
+    fn parse_captures(text: &str) -> Option<(&str, Vec<&str>)> {
+        let text = text.trim();
+        let (pattern, rest) = if let Some(quoted) = text.strip_prefix('"') {
+            let end = quoted.rfind('"')?;
+            (&quoted[..end], &quoted[end + 1..])
+        } else {
+            text.split_once(char::is_whitespace).unwrap_or((text, ""))
+        };
+
+        let names: Vec<&str> = rest.split_whitespace().collect();
+        if pattern.is_empty()
+            || names.is_empty()
+            || !names.iter().all(|name| is_identifier(name))
+        {
+            return None;
+        }
+        Some((pattern, names))
+    }
+
+    /// Parse a variable name followed by a pattern.
+    ///
+    /// The rest of the line after the name is the pattern.
+    fn parse_capture(text: &str) -> Option<(&str, &str)> {
+        let text = text.trim();
+        let (name, pattern) =
+            text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let pattern = pattern.trim_start();
+        if pattern.is_empty() || !is_identifier(name) {
+            return None;
+        }
+        Some((name, pattern))
+    }
+
+    /// Parse an assertion into a variable, operator and value.
+    ///
+    /// Operands may be separated by any amount of whitespace
+    /// and the operator must be `==` or `=~`.
+    pub(crate) fn parse_assertion(text: &str) -> Option<(&str, &str, &str)> {
+        let (name, rest) = text.trim().split_once(char::is_whitespace)?;
+        let (operator, expected) =
+            rest.trim_start().split_once(char::is_whitespace)?;
+        let expected = expected.trim();
+        if !matches!(operator, "==" | "=~") || expected.is_empty() {
+            return None;
+        }
+        Some((name, operator, expected))
+    }
+
+    /// Remove matching quotes and process escape sequences.
+    ///
+    /// Unquoted text is returned unchanged.
+    pub fn unquote(text: &str) -> Cow<'_, str> {
+        let quoted = text.len() >= 2
+            && ((text.starts_with('"') && text.ends_with('"'))
+                || (text.starts_with('\'') && text.ends_with('\'')));
+        if !quoted {
+            return Cow::Borrowed(text);
+        }
+
+        let inner = &text[1..text.len() - 1];
+        let mut value = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                value.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some(c @ ('\\' | '"' | '\'')) => value.push(c),
+                Some(c) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => value.push('\\'),
+            }
+        }
+        Cow::Owned(value)
+    }
+
+    /// Interpolate variables in a value.
+    ///
+    /// Captured variables take precedence over environment variables.
+    pub(crate) fn interpolate<'a>(
+        value: &'a str,
+        variables: &HashMap<String, String>,
+    ) -> Result<Cow<'a, str>> {
         if value.contains('$') {
             let mut s = String::new();
             let mut lex = EnvVars::lexer(value);
             let mut next_token = lex.next();
             while let Some(token) = next_token.take() {
-                let token = token?;
+                let token = token.map_err(|_| lex_error(&lex))?;
                 match token {
                     EnvVars::Var => {
                         let var = lex.slice();
-                        if let Ok(val) = std::env::var(&var[1..]) {
-                            s.push_str(&val);
-                        } else {
-                            s.push_str(var);
+                        match Self::lookup(&var[1..], variables) {
+                            Some(val) => s.push_str(&val),
+                            None => s.push_str(var),
+                        }
+                    }
+                    EnvVars::Braced => {
+                        let var = lex.slice();
+                        match Self::lookup(&var[2..var.len() - 1], variables)
+                        {
+                            Some(val) => s.push_str(&val),
+                            None => s.push_str(var),
+                        }
+                    }
+                    EnvVars::BracedDefault => {
+                        let var = lex.slice();
+                        let (name, default) = var[2..var.len() - 1]
+                            .split_once(":-")
+                            .unwrap_or_default();
+                        match Self::lookup(name, variables) {
+                            Some(val) if !val.is_empty() => s.push_str(&val),
+                            _ => s.push_str(default),
                         }
                     }
                     _ => s.push_str(lex.slice()),
@@ -284,4 +1054,66 @@ impl ScriptParser {
             Ok(Cow::Borrowed(value))
         }
     }
+
+    /// Find the value of a captured or environment variable.
+    fn lookup(
+        name: &str,
+        variables: &HashMap<String, String>,
+    ) -> Option<String> {
+        variables
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interpolate(value: &str) -> String {
+        let mut variables = HashMap::new();
+        variables.insert("NAME".to_owned(), "foo".to_owned());
+        variables.insert("EMPTY".to_owned(), String::new());
+        ScriptParser::interpolate(value, &variables)
+            .unwrap()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_interpolate_braced() {
+        assert_eq!("foo", interpolate("${NAME}"));
+        assert_eq!("foobar", interpolate("${NAME}bar"));
+        assert_eq!("foo", interpolate("$NAME"));
+        assert_eq!("${ANTICIPATE_UNSET}", interpolate("${ANTICIPATE_UNSET}"));
+    }
+
+    #[test]
+    fn test_interpolate_default() {
+        assert_eq!("foo", interpolate("${NAME:-bar}"));
+        assert_eq!("bar", interpolate("${ANTICIPATE_UNSET:-bar}"));
+        assert_eq!("bar", interpolate("${EMPTY:-bar}"));
+        assert_eq!("", interpolate("${ANTICIPATE_UNSET:-}"));
+        assert_eq!("a b", interpolate("${ANTICIPATE_UNSET:-a b}"));
+    }
+
+    #[test]
+    fn test_interpolate_braced_edge_cases() {
+        assert_eq!("${}", interpolate("${}"));
+        assert_eq!("${NAME", interpolate("${NAME"));
+        assert_eq!("${:-bar}", interpolate("${:-bar}"));
+        assert_eq!("$", interpolate("$"));
+    }
+
+    #[test]
+    fn test_interpolate_lex_error() {
+        let variables = HashMap::new();
+        let err = ScriptParser::interpolate("echo $NAME\nls", &variables)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Lex(LexError::UnexpectedToken(10, ref text)) if text == "\n"
+        ));
+        assert_eq!("unexpected token at byte 10: '\n'", err.to_string());
+    }
 }
@@ -0,0 +1,401 @@
+//! Results for asciinema recordings.
+use crate::{Error, Result};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Recording file written by asciinema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordingResult {
+    /// Path to the cast file.
+    pub path: PathBuf,
+    /// Size of the cast file in bytes.
+    pub bytes: u64,
+    /// Timestamp of the last event in seconds.
+    pub duration: Option<f64>,
+}
+
+impl RecordingResult {
+    /// Read the size and duration of a cast file.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let contents = std::fs::read_to_string(&path)?;
+        Ok(Self {
+            bytes: contents.len() as u64,
+            duration: last_event_time(&contents),
+            path,
+        })
+    }
+}
+
+impl fmt::Display for RecordingResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} bytes", self.path.display(), self.bytes)?;
+        if let Some(duration) = self.duration {
+            write!(f, ", {:.2}s", duration)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Format for rendering a cast file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// Animated SVG rendered with `svg-term`.
+    Svg,
+    /// Animated GIF rendered with `agg`.
+    Gif,
+}
+
+impl RenderFormat {
+    /// File extension for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::Gif => "gif",
+        }
+    }
+
+    fn command(&self, input: &Path, output: &Path) -> Command {
+        match self {
+            Self::Svg => {
+                let mut cmd = Command::new("svg-term");
+                cmd.arg("--in").arg(input).arg("--out").arg(output);
+                cmd
+            }
+            Self::Gif => {
+                let mut cmd = Command::new("agg");
+                cmd.arg(input).arg(output);
+                cmd
+            }
+        }
+    }
+}
+
+/// Render a cast file to a sibling file in another format.
+///
+/// Returns the path to the rendered file or `None` when the
+/// cast does not contain any events.
+pub fn render_cast(
+    path: impl AsRef<Path>,
+    format: RenderFormat,
+) -> Result<Option<PathBuf>> {
+    let path = path.as_ref();
+    let recording = RecordingResult::new(path)?;
+    if recording.duration.is_none() {
+        tracing::debug!(path = ?path, "render skip empty cast");
+        return Ok(None);
+    }
+
+    let output = path.with_extension(format.extension());
+    let mut cmd = format.command(path, &output);
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    tracing::debug!(program = %program, output = ?output, "render");
+    let status = match cmd.status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::RenderToolMissing(program));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !status.success() {
+        return Err(Error::RenderFailed(program, status));
+    }
+    Ok(Some(output))
+}
+
+/// Determine if a program can be found in `PATH`.
+///
+/// Programs given as a path are checked directly.
+pub(crate) fn program_exists(program: &str) -> bool {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return is_executable(path);
+    }
+    std::env::var_os("PATH")
+        .map(|paths| {
+            std::env::split_paths(&paths).any(|dir| {
+                let path = dir.join(program);
+                is_executable(&path)
+                    || (cfg!(windows)
+                        && is_executable(&path.with_extension("exe")))
+            })
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(windows)]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Timestamp of the last event in a cast file.
+///
+/// Events are JSON arrays that start with the time in
+/// seconds, the header line is an object so it is skipped.
+fn last_event_time(contents: &str) -> Option<f64> {
+    contents
+        .lines()
+        .rev()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .and_then(event_time)
+}
+
+/// Timestamp of an event line.
+fn event_time(line: &str) -> Option<f64> {
+    line.trim()
+        .strip_prefix('[')
+        .and_then(|event| event.split(',').next())
+        .and_then(|time| time.trim().parse().ok())
+}
+
+/// Marker event for a section of a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Marker {
+    /// Time in seconds since the recording started.
+    pub time: f64,
+    /// Label for the marker.
+    pub label: String,
+}
+
+/// Insert marker events into a cast file.
+pub(crate) fn write_markers(
+    filename: impl AsRef<Path>,
+    markers: &[Marker],
+) -> io::Result<()> {
+    let contents = std::fs::read_to_string(filename.as_ref())?;
+    std::fs::write(filename.as_ref(), insert_markers(&contents, markers))
+}
+
+/// Merge marker events into the events of a cast.
+///
+/// Markers are placed before the first event with a later
+/// time, markers after the last event use the time of the
+/// last event so they are not removed by players.
+fn insert_markers(contents: &str, markers: &[Marker]) -> String {
+    let last = last_event_time(contents).unwrap_or_default();
+    let mut pending = markers.iter().peekable();
+    let mut output = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        if let Some(time) = event_time(line) {
+            while let Some(marker) = pending.next_if(|m| m.time <= time) {
+                output.push_str(&marker_event(marker.time, &marker.label));
+            }
+        }
+        output.push_str(line);
+        output.push('\n');
+    }
+    for marker in pending {
+        output.push_str(&marker_event(marker.time.min(last), &marker.label));
+    }
+    output
+}
+
+/// Format a marker event line.
+fn marker_event(time: f64, label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => escaped.push(c),
+        }
+    }
+    format!("[{:.6}, \"m\", \"{}\"]\n", time, escaped)
+}
+
+/// Escaped clear screen sequence in a cast event.
+const CLEAR_SCREEN: &str = "\\u001b[2J";
+
+/// Remove the events before the screen was first cleared.
+///
+/// Event times are shifted so the cast starts when the screen
+/// was cleared, returns the time of the clear event in the
+/// original cast or `None` if the screen was never cleared.
+pub(crate) fn trim_intro(
+    filename: impl AsRef<Path>,
+) -> io::Result<Option<f64>> {
+    let contents = std::fs::read_to_string(filename.as_ref())?;
+    let Some((contents, offset)) = cut_intro(&contents) else {
+        return Ok(None);
+    };
+    std::fs::write(filename.as_ref(), contents)?;
+    Ok(Some(offset))
+}
+
+/// Cut the events of a cast before the first clear screen.
+fn cut_intro(contents: &str) -> Option<(String, f64)> {
+    let mut lines = contents.lines();
+    let header = lines.next()?;
+    let events: Vec<&str> = lines.collect();
+    let index = events.iter().position(|line| {
+        line.contains(r#", "o", ""#) && line.contains(CLEAR_SCREEN)
+    })?;
+    let offset = event_time(events[index])?;
+
+    let mut output = String::with_capacity(contents.len());
+    output.push_str(header);
+    output.push('\n');
+    for (position, line) in events[index..].iter().enumerate() {
+        let (Some(time), Some(comma)) = (event_time(line), line.find(','))
+        else {
+            continue;
+        };
+        let rest = &line[comma..];
+        // Output before the clear was written by the hidden commands
+        let rest = match rest.find(CLEAR_SCREEN) {
+            Some(clear) if position == 0 => {
+                format!(r#", "o", "{}"#, &rest[clear..])
+            }
+            _ => rest.to_owned(),
+        };
+        output.push_str(&format!("[{:.6}{}\n", time - offset, rest));
+    }
+    Some((output, offset))
+}
+
+/// Truncate a number of lines from the end of a cast file.
+pub(crate) fn trim_exit(
+    filename: impl AsRef<Path>,
+    trim_lines: u64,
+) -> io::Result<()> {
+    let mut file = File::open(filename.as_ref())?;
+    let file_size = file.seek(SeekFrom::End(0))?;
+    let mut cursor = file_size;
+    let mut bytes_read = 0;
+    let mut num_lines = 0;
+
+    // Read backwards and count newlines
+    loop {
+        if cursor > 0 {
+            cursor -= 1;
+            file.seek(SeekFrom::Start(cursor))?;
+        } else {
+            break;
+        }
+
+        let mut buf = [0; 1];
+        let byte = file.read_exact(&mut buf);
+        if byte.is_err() {
+            break;
+        }
+
+        if &buf == b"\n" {
+            num_lines += 1;
+        }
+
+        if num_lines == trim_lines + 1 {
+            break;
+        }
+
+        bytes_read += 1;
+    }
+
+    // Truncate the file
+    if bytes_read < file_size {
+        let file = OpenOptions::new().write(true).open(filename.as_ref())?;
+        let new_len = file_size - bytes_read;
+        tracing::debug!(
+            len = %new_len,
+            file = ?filename.as_ref(),
+            "truncate",
+        );
+        file.set_len(new_len)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cut_intro, insert_markers, last_event_time, render_cast, Marker,
+        RenderFormat,
+    };
+
+    #[test]
+    fn cast_duration() {
+        let cast = r#"{"version": 2, "width": 80, "height": 24}
+[0.25, "o", "$ "]
+[1.5, "o", "exit\r\n"]
+"#;
+        assert_eq!(Some(1.5), last_event_time(cast));
+
+        let cast = r#"{"version": 2, "width": 80, "height": 24}
+"#;
+        assert_eq!(None, last_event_time(cast));
+    }
+
+    #[test]
+    fn cast_markers() {
+        let cast = r#"{"version": 2, "width": 80, "height": 24}
+[0.25, "o", "$ "]
+[1.5, "o", "exit\r\n"]
+"#;
+        let markers = vec![
+            Marker {
+                time: 1.0,
+                label: "build".to_owned(),
+            },
+            Marker {
+                time: 2.0,
+                label: "say \"bye\"".to_owned(),
+            },
+        ];
+        let expected = r#"{"version": 2, "width": 80, "height": 24}
+[0.25, "o", "$ "]
+[1.000000, "m", "build"]
+[1.5, "o", "exit\r\n"]
+[1.500000, "m", "say \"bye\""]
+"#;
+        assert_eq!(expected, insert_markers(cast, &markers));
+    }
+
+    #[test]
+    fn cast_intro() {
+        let cast = r#"{"version": 2, "width": 80, "height": 24}
+[0.25, "o", "$ "]
+[0.5, "o", "export NAME=foo\r\n$ "]
+[0.75, "o", "clear\r\n\u001b[H\u001b[2J$ "]
+[1.5, "o", "echo $NAME"]
+"#;
+        let expected = r#"{"version": 2, "width": 80, "height": 24}
+[0.000000, "o", "\u001b[2J$ "]
+[0.750000, "o", "echo $NAME"]
+"#;
+        let (contents, offset) = cut_intro(cast).unwrap();
+        assert_eq!(expected, contents);
+        assert_eq!(0.75, offset);
+
+        let cast = r#"{"version": 2, "width": 80, "height": 24}
+[0.25, "o", "$ "]
+"#;
+        assert!(cut_intro(cast).is_none());
+    }
+
+    #[test]
+    fn render_empty_cast() {
+        let path = std::env::temp_dir()
+            .join(format!("anticipate-empty-{}.cast", std::process::id()));
+        std::fs::write(&path, "{\"version\": 2}\n").unwrap();
+        let rendered = render_cast(&path, RenderFormat::Gif).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(rendered.is_none());
+    }
+}
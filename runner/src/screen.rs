@@ -0,0 +1,88 @@
+//! Render program output to a grid of characters.
+#[cfg(feature = "screenshot")]
+use std::sync::{Arc, Mutex};
+
+/// Visible contents of the terminal.
+///
+/// Output is interpreted by a VT100 parser.
+#[cfg(feature = "screenshot")]
+#[derive(Clone)]
+pub(crate) struct Screen(Arc<Mutex<vt100::Parser>>);
+
+#[cfg(feature = "screenshot")]
+impl Screen {
+    /// Create a blank screen.
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self(Arc::new(Mutex::new(vt100::Parser::new(rows, cols, 0))))
+    }
+
+    /// Update the screen with output from the program.
+    pub fn process(&self, data: &[u8]) {
+        self.0.lock().unwrap().process(data);
+    }
+
+    /// Change the size of the screen.
+    pub fn resize(&self, cols: u16, rows: u16) {
+        self.0.lock().unwrap().set_size(rows, cols);
+    }
+
+    /// Visible text with trailing whitespace removed.
+    pub fn contents(&self) -> String {
+        let parser = self.0.lock().unwrap();
+        let screen = parser.screen();
+        let (_, cols) = screen.size();
+        let mut lines: Vec<String> = screen
+            .rows(0, cols)
+            .map(|line| line.trim_end().to_owned())
+            .collect();
+        while lines.last().map(|l| l.is_empty()).unwrap_or(false) {
+            lines.pop();
+        }
+        let mut text = lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+/// Screen that discards output when the screenshot
+/// feature is not enabled.
+#[cfg(not(feature = "screenshot"))]
+#[derive(Clone)]
+pub(crate) struct Screen;
+
+#[cfg(not(feature = "screenshot"))]
+impl Screen {
+    /// Create a screen.
+    pub fn new(_cols: u16, _rows: u16) -> Self {
+        Self
+    }
+
+    /// Discard output from the program.
+    pub fn process(&self, _data: &[u8]) {}
+
+    /// Ignore the size of the screen.
+    pub fn resize(&self, _cols: u16, _rows: u16) {}
+}
+
+#[cfg(all(test, feature = "screenshot"))]
+mod test {
+    use super::Screen;
+
+    #[test]
+    fn screen_contents() {
+        let screen = Screen::new(10, 3);
+        screen.process(b"\x1b]0;title\x07$ echo hi\r\nhi\r\n");
+        assert_eq!("$ echo hi\nhi\n", screen.contents());
+
+        // Wrap, scroll and erase
+        screen.process(b"$ 0123456789ab\r\n");
+        assert_eq!("$ 01234567\n89ab\n", screen.contents());
+        screen.process(b"\x1b[2J\x1b[H\x1b[1mbold\x1b[0m");
+        assert_eq!("bold\n", screen.contents());
+
+        // Line editing and split UTF-8 sequences
+        screen.process(b"\rab\x08\x1b[K\xc3");
+        screen.process(b"\xa9");
+        assert_eq!("a\u{e9}\n", screen.contents());
+    }
+}
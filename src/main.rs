@@ -2,21 +2,35 @@
 //! using [asciinema](https://asciinema.org/).
 //!
 //! For programmatic access use the [anticipate-core](https://docs.rs/anticipate-core) crate, see [the repository](https://github.com/tmpfs/anticipate/) for examples.
-use anticipate_runner::{CinemaOptions, InterpreterOptions, ScriptFile};
+use anticipate_runner::{
+    render_cast, Checksum, CinemaOptions, CommentStyle, Golden, Instruction,
+    InterpreterOptions, RenderFormat, RunReport, ScriptFile, SharedSession,
+    TestCase, Transcript,
+};
 use anyhow::{bail, Result};
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use rayon::prelude::*;
 use std::{
-    fs::{File, OpenOptions},
-    io::{self, Read, Seek, SeekFrom},
+    io::IsTerminal,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
+use tracing::Level;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 const TICK: &str = "✓";
 const ERROR: &str = "Err";
 
+/// Print messages to stderr, set when a recording is
+/// streamed to stdout.
+static MESSAGES_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
 #[doc(hidden)]
 fn main() -> Result<()> {
     if let Err(e) = start() {
@@ -31,32 +45,65 @@ fn fail(e: impl std::fmt::Display + std::fmt::Debug) {
     std::process::exit(1);
 }
 
+/// Determine if messages should be colored.
+///
+/// Color is disabled by the flag, a non-empty `NO_COLOR`
+/// variable or when stdout is not a terminal.
+fn use_color(no_color: bool) -> bool {
+    let no_color_env =
+        std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && !no_color_env && std::io::stdout().is_terminal()
+}
+
 /// Print a success message.
 pub fn success(msg: impl AsRef<str>) {
     let out = format!("{} {}", msg.as_ref().green(), TICK.green());
-    println!("{}", out);
+    message(out);
 }
 
 /// Print a info message.
 pub fn info(msg: impl AsRef<str>) {
     let out = format!("{}", msg.as_ref().yellow());
-    println!("{}", out);
+    message(out);
 }
 
 /// Print an error message for failure.
 pub fn error(msg: impl AsRef<str>) {
     let out = format!("{} {}", ERROR.red(), msg.as_ref());
-    println!("{}", out);
+    message(out);
+}
+
+fn message(out: String) {
+    if MESSAGES_TO_STDERR.load(Ordering::SeqCst) {
+        eprintln!("{}", out);
+    } else {
+        println!("{}", out);
+    }
 }
 
 #[doc(hidden)]
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Anticipate {
+    /// Disable colored output.
+    #[clap(long, global = true)]
+    no_color: bool,
+
     #[clap(subcommand)]
     cmd: Command,
 }
 
+/// Format for log output.
+#[doc(hidden)]
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable logs.
+    #[default]
+    Human,
+    /// Structured JSON logs.
+    Json,
+}
+
 #[doc(hidden)]
 #[derive(Debug, Subcommand)]
 pub enum Command {
@@ -66,10 +113,31 @@ pub enum Command {
         #[clap(short, long, env = "ANTICIPATE_LOG", hide_env_values = true)]
         log: bool,
 
+        /// Format for log output.
+        #[clap(
+            long,
+            env = "ANTICIPATE_LOG_FORMAT",
+            hide_env_values = true,
+            default_value = "human"
+        )]
+        log_format: LogFormat,
+
+        /// Increase log verbosity, -v for debug and -vv for trace.
+        #[clap(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
         /// Parse scripts in parallel.
         #[clap(short, long)]
         parallel: bool,
 
+        /// Maximum number of files to process in parallel.
+        #[clap(short, long, requires = "parallel")]
+        jobs: Option<NonZeroUsize>,
+
+        /// Directory to search for include files.
+        #[clap(long)]
+        include_path: Vec<PathBuf>,
+
         /// Input file paths.
         input: Vec<PathBuf>,
     },
@@ -79,6 +147,19 @@ pub enum Command {
         #[clap(short, long, env = "ANTICIPATE_LOG", hide_env_values = true)]
         log: bool,
 
+        /// Format for log output.
+        #[clap(
+            long,
+            env = "ANTICIPATE_LOG_FORMAT",
+            hide_env_values = true,
+            default_value = "human"
+        )]
+        log_format: LogFormat,
+
+        /// Increase log verbosity, -v for debug and -vv for trace.
+        #[clap(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
         /// Scripts to run beforehand in sequence.
         #[clap(short, long)]
         setup: Vec<PathBuf>,
@@ -91,10 +172,34 @@ pub enum Command {
         #[clap(short, long)]
         parallel: bool,
 
+        /// Maximum number of files to process in parallel.
+        #[clap(short, long, requires = "parallel")]
+        jobs: Option<NonZeroUsize>,
+
         /// Timeout for the pseudo-terminal.
         #[clap(short, long, default_value = "5000")]
         timeout: u64,
 
+        /// Multiplier for timeouts in slow environments.
+        #[clap(
+            long,
+            env = "ANTICIPATE_TIMEOUT_MULTIPLIER",
+            hide_env_values = true,
+            default_value = "1.0",
+            value_parser = parse_multiplier
+        )]
+        timeout_multiplier: f64,
+
+        /// Maximum duration for each script in milliseconds.
+        #[clap(long)]
+        max_duration: Option<u64>,
+
+        /// Timeout waiting for the program to exit in milliseconds.
+        ///
+        /// Defaults to the timeout.
+        #[clap(long)]
+        eof_timeout: Option<u64>,
+
         /// Echo input and output.
         #[clap(short, long, env = "ANTICIPATE_ECHO", hide_env_values = true)]
         echo: bool,
@@ -108,10 +213,94 @@ pub enum Command {
         )]
         format: bool,
 
+        /// Write input and output logs to a file instead of stdout.
+        #[clap(long, value_name = "FILE")]
+        log_io: Option<PathBuf>,
+
         /// Print comments.
         #[clap(long)]
         print_comments: bool,
 
+        /// Match newlines in expect patterns against CRLF output.
+        #[clap(long)]
+        normalize_newlines: bool,
+
+        /// Shell command to run before each script.
+        #[clap(long)]
+        pre_command: Vec<String>,
+
+        /// Shell command to run after each script.
+        #[clap(long)]
+        post_command: Vec<String>,
+
+        /// Compare the program output with a golden file.
+        #[clap(long)]
+        golden: Option<PathBuf>,
+
+        /// Rewrite the golden file with the program output.
+        #[clap(long, requires = "golden")]
+        update_golden: bool,
+
+        /// Strip ANSI escape sequences before comparing output.
+        #[clap(long, requires = "golden")]
+        strip_ansi: bool,
+
+        /// Keep running remaining files after a failure.
+        ///
+        /// Setup scripts always stop at the first failure.
+        #[clap(long)]
+        continue_on_error: bool,
+
+        /// Exit with an error when a teardown script fails.
+        #[clap(long)]
+        strict_teardown: bool,
+
+        /// Print each instruction before it is executed.
+        #[clap(long)]
+        trace: bool,
+
+        /// Stop after the first instruction whose name or
+        /// argument contains the text.
+        #[clap(long, value_name = "SUBSTRING")]
+        until: Option<String>,
+
+        /// Write a JUnit XML report to a file.
+        #[clap(long)]
+        report: Option<PathBuf>,
+
+        /// Do not send end of transmission when a script finishes.
+        #[clap(long)]
+        no_eof: bool,
+
+        /// Fail when a line sent to the program is longer than
+        /// the number of bytes.
+        #[clap(long, value_name = "BYTES")]
+        max_line_length: Option<usize>,
+
+        /// Run setup, scripts and teardown in a single shell.
+        ///
+        /// Environment variables and the working directory
+        /// are kept between scripts, a script that exits or
+        /// crashes the shell fails the scripts that follow.
+        #[clap(long, conflicts_with_all = ["parallel", "golden"])]
+        shared_session: bool,
+
+        /// Directory to search for include files.
+        #[clap(long)]
+        include_path: Vec<PathBuf>,
+
+        /// Working directory for the spawned program.
+        #[clap(long)]
+        cwd: Option<PathBuf>,
+
+        /// Number of columns for the pseudo-terminal.
+        #[clap(long, requires = "rows")]
+        cols: Option<u16>,
+
+        /// Number of rows for the pseudo-terminal.
+        #[clap(long, requires = "cols")]
+        rows: Option<u16>,
+
         /// Input file paths.
         input: Vec<PathBuf>,
     },
@@ -123,6 +312,19 @@ pub enum Command {
         #[clap(short, long, env = "ANTICIPATE_LOG", hide_env_values = true)]
         log: bool,
 
+        /// Format for log output.
+        #[clap(
+            long,
+            env = "ANTICIPATE_LOG_FORMAT",
+            hide_env_values = true,
+            default_value = "human"
+        )]
+        log_format: LogFormat,
+
+        /// Increase log verbosity, -v for debug and -vv for trace.
+        #[clap(short, long, action = clap::ArgAction::Count)]
+        verbose: u8,
+
         /// Scripts to record beforehand in sequence.
         #[clap(short, long)]
         setup: Vec<PathBuf>,
@@ -135,10 +337,34 @@ pub enum Command {
         #[clap(short, long)]
         parallel: bool,
 
+        /// Maximum number of files to process in parallel.
+        #[clap(short, long, requires = "parallel")]
+        jobs: Option<NonZeroUsize>,
+
         /// Timeout for the pseudo-terminal.
         #[clap(short, long, default_value = "5000")]
         timeout: u64,
 
+        /// Multiplier for timeouts in slow environments.
+        #[clap(
+            long,
+            env = "ANTICIPATE_TIMEOUT_MULTIPLIER",
+            hide_env_values = true,
+            default_value = "1.0",
+            value_parser = parse_multiplier
+        )]
+        timeout_multiplier: f64,
+
+        /// Maximum duration for each script in milliseconds.
+        #[clap(long)]
+        max_duration: Option<u64>,
+
+        /// Timeout waiting for the program to exit in milliseconds.
+        ///
+        /// Defaults to the timeout.
+        #[clap(long)]
+        eof_timeout: Option<u64>,
+
         /// Echo input and output.
         #[clap(short, long, env = "ANTICIPATE_ECHO", hide_env_values = true)]
         echo: bool,
@@ -152,14 +378,42 @@ pub enum Command {
         )]
         format: bool,
 
+        /// Write input and output logs to a file instead of stdout.
+        #[clap(long, value_name = "FILE")]
+        log_io: Option<PathBuf>,
+
         /// Print comments.
         #[clap(long)]
         print_comments: bool,
 
+        /// Match newlines in expect patterns against CRLF output.
+        #[clap(long)]
+        normalize_newlines: bool,
+
+        /// Type comments and clear the line without executing them.
+        #[clap(long, requires = "print_comments")]
+        annotate_comments: bool,
+
+        /// Shell command to run before each script.
+        #[clap(long)]
+        pre_command: Vec<String>,
+
+        /// Shell command to run after each script.
+        #[clap(long)]
+        post_command: Vec<String>,
+
         /// Overwrite existing recordings.
         #[clap(short, long)]
         overwrite: bool,
 
+        /// Skip recordings when the script and options are unchanged.
+        #[clap(long)]
+        skip_unchanged: bool,
+
+        /// Write a marker to the cast for each section.
+        #[clap(long)]
+        section_markers: bool,
+
         /// Delay between keystrokes.
         #[clap(short, long, default_value = "75")]
         delay: u64,
@@ -168,33 +422,62 @@ pub enum Command {
         #[clap(long, default_value = "15.0")]
         deviation: f64,
 
+        /// Seed for keystroke delays to make recordings reproducible.
+        #[clap(long)]
+        seed: Option<u64>,
+
+        /// Flush typed text once per line instead of every keystroke.
+        #[clap(long)]
+        flush_per_line: bool,
+
         /// Prompt for the shell.
         #[clap(long, default_value = "➜ ")]
         prompt: String,
 
-        /// Shell command.
+        /// Shell command, split on whitespace.
         #[clap(long, default_value = "sh -noprofile -norc")]
         shell: String,
 
+        /// Extra argument for the shell, may be repeated.
+        #[clap(long, allow_hyphen_values = true)]
+        shell_arg: Vec<String>,
+
         /// Type pragma commands.
         #[clap(long)]
         type_pragma: bool,
 
+        /// Do not run pragma commands in the recorded shell.
+        #[clap(long, conflicts_with = "type_pragma")]
+        skip_pragma: bool,
+
         /// Number of lines to trim from end of recording.
         #[clap(long, default_value = "1")]
         trim_lines: u64,
 
-        /// Number of terminal columns.
-        #[clap(long, default_value = "80")]
-        cols: u64,
+        /// Render the recording to svg (svg-term) or gif (agg).
+        #[clap(long, value_parser = parse_render)]
+        render: Option<RenderFormat>,
 
-        /// Number of terminal rows.
-        #[clap(long, default_value = "24")]
-        rows: u64,
+        /// Number of terminal columns, defaults to `COLUMNS` or 80.
+        #[clap(long)]
+        cols: Option<u64>,
 
-        /// Directory for recordings.
+        /// Number of terminal rows, defaults to `LINES` or 24.
+        #[clap(long)]
+        rows: Option<u64>,
+
+        /// Directory for recordings, use `-` to write the
+        /// recording to stdout.
         output: PathBuf,
 
+        /// Directory to search for include files.
+        #[clap(long)]
+        include_path: Vec<PathBuf>,
+
+        /// Working directory for the spawned program.
+        #[clap(long)]
+        cwd: Option<PathBuf>,
+
         /// Input file paths.
         input: Vec<PathBuf>,
     },
@@ -203,351 +486,916 @@ pub enum Command {
 #[doc(hidden)]
 fn start() -> Result<()> {
     let args = Anticipate::parse();
+    if !use_color(args.no_color) {
+        colored::control::set_override(false);
+    }
     match args.cmd {
         Command::Parse {
             input,
             log,
+            log_format,
+            verbose,
             parallel,
+            jobs,
+            include_path,
         } => {
-            if log {
-                init_subscriber()?;
+            let log_level = verbosity_level(verbose);
+            if log || log_level.is_some() {
+                init_subscriber(log_format, log_level)?;
             }
 
             let files = check_files(input)?;
+            let progress = Progress::new(files.len());
 
             if parallel {
-                files.par_iter().for_each(|(input_file, file_name)| {
-                    if let Err(e) = parse(input_file, file_name) {
-                        fail(e);
-                    }
-                });
+                with_jobs(jobs, || {
+                    files.par_iter().for_each(|(input_file, file_name)| {
+                        if let Err(e) = parse(
+                            input_file,
+                            file_name,
+                            &include_path,
+                            &progress,
+                        ) {
+                            fail(e);
+                        }
+                    });
+                })?;
             } else {
                 for (input_file, file_name) in files {
-                    parse(&input_file, &file_name)?;
+                    parse(&input_file, &file_name, &include_path, &progress)?;
                 }
             }
         }
         Command::Run {
             input,
             timeout,
+            timeout_multiplier,
+            max_duration,
+            eof_timeout,
             parallel,
+            jobs,
             log,
+            log_format,
+            verbose,
             echo,
             format,
+            log_io,
             print_comments,
+            normalize_newlines,
             setup,
             teardown,
+            golden,
+            update_golden,
+            strip_ansi,
+            pre_command,
+            post_command,
+            continue_on_error,
+            strict_teardown,
+            trace,
+            until,
+            report,
+            no_eof,
+            max_line_length,
+            shared_session,
+            include_path,
+            cwd,
+            cols,
+            rows,
         } => {
-            if log {
-                init_subscriber()?;
+            let log_level = verbosity_level(verbose);
+            if log || log_level.is_some() {
+                init_subscriber(log_format, log_level)?;
+            }
+            check_working_dir(cwd.as_deref())?;
+            truncate_log(log_io.as_deref())?;
+
+            if golden.is_some() && input.len() != 1 {
+                bail!("--golden requires a single input file");
             }
+            let golden = golden.map(|path| Golden::new(path, strip_ansi));
 
             let files = check_files(input)?;
-            if !setup.is_empty() {
-                let files = check_files(setup)?;
-                for (input_file, file_name) in files {
-                    run(
-                        &input_file,
-                        &file_name,
-                        timeout,
-                        echo,
-                        format,
-                        print_comments,
-                    )?;
+            let setup = check_files(setup)?;
+            let teardown = check_files(teardown)?;
+            let mut results = RunReport::new("anticipate");
+            let mut outcome = RunOutcome::default();
+            // Options are not cloneable so they are built for each file
+            let options = || {
+                let mut builder = InterpreterOptions::builder()
+                    .timeout(timeout)
+                    .timeout_multiplier(timeout_multiplier)
+                    .echo(echo)
+                    .format(format)
+                    .print_comments(print_comments)
+                    .normalize_newlines(normalize_newlines)
+                    .pre_commands(pre_command.clone())
+                    .post_commands(post_command.clone())
+                    .send_eof(!no_eof);
+                if let Some(limit) = max_duration {
+                    builder =
+                        builder.total_timeout(Duration::from_millis(limit));
+                }
+                if let Some(limit) = eof_timeout {
+                    builder =
+                        builder.eof_timeout(Duration::from_millis(limit));
                 }
+                if let Some(path) = &log_io {
+                    builder = builder.log_file(path);
+                }
+                if let Some(dir) = &cwd {
+                    builder = builder.working_dir(dir);
+                }
+                if let Some((cols, rows)) = cols.zip(rows) {
+                    builder = builder.size(cols, rows);
+                }
+                if let Some(level) = log_level {
+                    builder = builder.log_level(level);
+                }
+                if let Some(text) = &until {
+                    builder = builder.stop_after(text);
+                }
+                if let Some(length) = max_line_length {
+                    builder = builder.max_line_length(length);
+                }
+                if trace {
+                    builder =
+                        builder.on_instruction(Box::new(trace_instruction));
+                }
+                builder
+            };
+            let shared = if shared_session {
+                let session = SharedSession::spawn(options().build())?;
+                Some(Mutex::new(session))
+            } else {
+                None
+            };
+            let settings = RunSettings {
+                include_roots: &include_path,
+                shared: shared.as_ref(),
+                update_golden,
+            };
+            let run_file = |input_file: &PathBuf,
+                            file_name: &str,
+                            progress: &Progress,
+                            golden| {
+                run(
+                    input_file,
+                    file_name,
+                    progress,
+                    options().id(file_name).build(),
+                    golden,
+                    settings,
+                )
+            };
+
+            // Setup stops at the first failure and skips
+            // the scripts and teardown, --continue-on-error
+            // only applies to the scripts and teardown is
+            // always run in full
+            let progress = Progress::new(setup.len());
+            outcome.setup =
+                run_files(&setup, false, false, &mut results, |f, n| {
+                    run_file(f, n, &progress, None)
+                })
+                .err();
+
+            if outcome.setup.is_none() {
+                let progress = Progress::new(files.len());
+                outcome.main = with_jobs(jobs, || {
+                    run_files(
+                        &files,
+                        parallel,
+                        continue_on_error,
+                        &mut results,
+                        |f, n| run_file(f, n, &progress, golden.as_ref()),
+                    )
+                })
+                .and_then(|result| result)
+                .err();
+
+                // Teardown always runs every file so that
+                // cleanup is not skipped
+                let progress = Progress::new(teardown.len());
+                run_files(
+                    &teardown,
+                    false,
+                    true,
+                    &mut outcome.teardown,
+                    |f, n| run_file(f, n, &progress, None),
+                )?;
             }
 
-            if parallel {
-                files.par_iter().for_each(
-                    |(input_file, file_name)| match run(
-                        input_file,
-                        file_name,
-                        timeout,
-                        echo,
-                        format,
-                        print_comments,
-                    ) {
-                        Ok(_) => {}
-                        Err(e) => fail(e),
-                    },
-                );
-            } else {
-                for (input_file, file_name) in files {
-                    run(
-                        &input_file,
-                        &file_name,
-                        timeout,
-                        echo,
-                        format,
-                        print_comments,
-                    )?;
+            if let Some(session) = shared {
+                let session = session.into_inner().unwrap();
+                if let Err(e) = session.finish() {
+                    error(format!("shared session {}", e));
                 }
             }
 
-            if !teardown.is_empty() {
-                let files = check_files(teardown)?;
-                for (input_file, file_name) in files {
-                    run(
-                        &input_file,
-                        &file_name,
-                        timeout,
-                        echo,
-                        format,
-                        print_comments,
-                    )?;
-                }
+            if let Some(report) = &report {
+                // Teardown is reported even though failures only
+                // change the result with --strict-teardown
+                let mut suite = results.clone();
+                suite.cases.extend(outcome.teardown.cases.iter().cloned());
+                std::fs::write(report, suite.to_junit())?;
+                info(format!("Report {}", report.to_string_lossy()));
             }
+
+            outcome.finish(results, continue_on_error, strict_teardown)?;
         }
         Command::Record {
             parallel,
+            jobs,
             overwrite,
+            skip_unchanged,
+            section_markers,
             output,
             input,
             timeout,
+            timeout_multiplier,
+            max_duration,
+            eof_timeout,
             delay,
             prompt,
             shell,
+            shell_arg,
             type_pragma,
+            skip_pragma,
             trim_lines,
+            render,
             cols,
             rows,
             deviation,
+            seed,
+            flush_per_line,
             log,
+            log_format,
+            verbose,
             echo,
             format,
+            log_io,
             print_comments,
+            normalize_newlines,
+            annotate_comments,
             setup,
             teardown,
+            pre_command,
+            post_command,
+            include_path,
+            cwd,
         } => {
-            if log {
-                init_subscriber()?;
+            let log_level = verbosity_level(verbose);
+            if log || log_level.is_some() {
+                init_subscriber(log_format, log_level)?;
             }
+            check_working_dir(cwd.as_deref())?;
+            truncate_log(log_io.as_deref())?;
 
+            let comment_style = if annotate_comments {
+                CommentStyle::Annotate
+            } else {
+                CommentStyle::Execute
+            };
+
+            let mut shell = shell.split_whitespace().map(|s| s.to_owned());
+            let Some(shell_program) = shell.next() else {
+                bail!("--shell must not be empty");
+            };
             let cinema = CinemaOptions {
                 delay,
-                shell: shell.clone(),
+                shell_program,
+                shell_args: shell.chain(shell_arg).collect(),
                 type_pragma,
+                run_pragma: !skip_pragma,
                 deviation,
+                seed,
+                section_markers,
                 cols,
                 rows,
+                flush_per_keystroke: !flush_per_line,
             };
 
-            let files = check_recording_files(input, &output, overwrite)?;
+            // Record to a temporary directory so the cast is
+            // complete and trimmed before it is written to stdout
+            let stream = output.as_os_str() == "-";
+            let (output, _temp_dir) = if stream {
+                if input.len() != 1 {
+                    bail!("streaming to stdout requires a single input file");
+                }
+                // Logs and echoed output are also written to stdout
+                if echo
+                    || log
+                    || log_level.is_some()
+                    || render.is_some()
+                    || skip_unchanged
+                {
+                    bail!(
+                        "--echo, --log, --verbose, --render and \
+                        --skip-unchanged can not be used when \
+                        streaming to stdout"
+                    );
+                }
+                MESSAGES_TO_STDERR.store(true, Ordering::SeqCst);
+                let dir = std::env::temp_dir()
+                    .join(format!("anticipate-{}", std::process::id()));
+                std::fs::create_dir_all(&dir)?;
+                (dir.clone(), Some(TempDir(dir)))
+            } else {
+                (output, None)
+            };
+
+            let files = check_recording_files(
+                input,
+                &output,
+                overwrite,
+                skip_unchanged,
+            )?;
+            let streamed = stream.then(|| files[0].1.clone());
+            let cancel = interrupt_handler()?;
+            let settings = RecordSettings {
+                include_roots: &include_path,
+                trim_lines,
+                render,
+                overwrite,
+                skip_unchanged,
+            };
+            // Options are not cloneable so they are built for each file
+            let options = |output_file: &Path, file_name: &str| {
+                let mut builder = InterpreterOptions::builder()
+                    .recording(output_file, overwrite, cinema.clone())
+                    .timeout(timeout)
+                    .timeout_multiplier(timeout_multiplier)
+                    .echo(echo)
+                    .format(format)
+                    .print_comments(print_comments)
+                    .normalize_newlines(normalize_newlines)
+                    .prompt(prompt.as_str())
+                    .id(file_name)
+                    .comment_style(comment_style)
+                    .pre_commands(pre_command.clone())
+                    .post_commands(post_command.clone());
+                if let Some(limit) = max_duration {
+                    builder =
+                        builder.total_timeout(Duration::from_millis(limit));
+                }
+                if let Some(limit) = eof_timeout {
+                    builder =
+                        builder.eof_timeout(Duration::from_millis(limit));
+                }
+                if let Some(cancel) = &cancel {
+                    builder = builder.cancel(Arc::clone(cancel));
+                }
+                if let Some(path) = &log_io {
+                    builder = builder.log_file(path);
+                }
+                if let Some(dir) = &cwd {
+                    builder = builder.working_dir(dir);
+                }
+                if let Some(level) = log_level {
+                    builder = builder.log_level(level);
+                }
+                builder.build()
+            };
             if !setup.is_empty() {
-                let files = check_recording_files(setup, &output, overwrite)?;
+                let files = check_recording_files(
+                    setup,
+                    &output,
+                    overwrite,
+                    skip_unchanged,
+                )?;
+                let progress = Progress::new(files.len());
                 for (input_file, output_file, file_name) in files {
                     record(
                         &input_file,
-                        &output_file,
                         &file_name,
-                        &cinema,
-                        timeout,
-                        trim_lines,
-                        overwrite,
-                        echo,
-                        format,
-                        &prompt,
-                        print_comments,
+                        &progress,
+                        options(&output_file, &file_name),
+                        settings,
                     )?;
                 }
             }
 
+            let progress = Progress::new(files.len());
             if parallel {
-                files.par_iter().for_each(
-                    |(input_file, output_file, file_name)| match record(
-                        input_file,
-                        output_file,
-                        file_name,
-                        &cinema,
-                        timeout,
-                        trim_lines,
-                        overwrite,
-                        echo,
-                        format,
-                        &prompt,
-                        print_comments,
-                    ) {
-                        Ok(_) => {}
-                        Err(e) => fail(e),
-                    },
-                );
+                with_jobs(jobs, || {
+                    files.par_iter().try_for_each(
+                        |(input_file, output_file, file_name)| {
+                            let result = record(
+                                input_file,
+                                file_name,
+                                &progress,
+                                options(output_file, file_name),
+                                settings,
+                            );
+                            match result {
+                                // Let other recordings finish writing
+                                Err(_) if is_interrupted(cancel.as_ref()) => {
+                                    Ok(())
+                                }
+                                result => result,
+                            }
+                        },
+                    )
+                })
+                .and_then(|result| result)?;
+                if is_interrupted(cancel.as_ref()) {
+                    bail!("recording interrupted");
+                }
             } else {
                 for (input_file, output_file, file_name) in files {
                     record(
                         &input_file,
-                        &output_file,
                         &file_name,
-                        &cinema,
-                        timeout,
-                        trim_lines,
-                        overwrite,
-                        echo,
-                        format,
-                        &prompt,
-                        print_comments,
+                        &progress,
+                        options(&output_file, &file_name),
+                        settings,
                     )?;
                 }
             }
 
             if !teardown.is_empty() {
-                let files =
-                    check_recording_files(teardown, &output, overwrite)?;
+                let files = check_recording_files(
+                    teardown,
+                    &output,
+                    overwrite,
+                    skip_unchanged,
+                )?;
+                let progress = Progress::new(files.len());
                 for (input_file, output_file, file_name) in files {
                     record(
                         &input_file,
-                        &output_file,
                         &file_name,
-                        &cinema,
-                        timeout,
-                        trim_lines,
-                        overwrite,
-                        echo,
-                        format,
-                        &prompt,
-                        print_comments,
+                        &progress,
+                        options(&output_file, &file_name),
+                        settings,
                     )?;
                 }
             }
+
+            if let Some(cast) = streamed {
+                let mut file = std::fs::File::open(&cast)?;
+                std::io::copy(&mut file, &mut std::io::stdout().lock())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of the setup, main and teardown phases of a run.
+#[derive(Default)]
+struct RunOutcome {
+    /// First setup failure.
+    setup: Option<anyhow::Error>,
+    /// First failure when not continuing on error.
+    main: Option<anyhow::Error>,
+    /// Results for the teardown files.
+    teardown: RunReport,
+}
+
+impl RunOutcome {
+    /// Report failures and determine the result of the run.
+    ///
+    /// Teardown failures are printed but only change the
+    /// result when `strict_teardown` is set.
+    fn finish(
+        self,
+        results: RunReport,
+        continue_on_error: bool,
+        strict_teardown: bool,
+    ) -> Result<()> {
+        if let Some(e) = self.setup {
+            bail!("setup failed, skipped scripts and teardown: {}", e);
+        }
+
+        for case in &self.teardown.cases {
+            if let Some(e) = &case.failure {
+                error(format!("teardown {} {}", case.name, e));
+            }
         }
+
+        if let Some(e) = self.main {
+            return Err(e);
+        }
+
+        let summary = if continue_on_error {
+            summary(results)
+        } else {
+            Ok(())
+        };
+
+        let failed = self.teardown.failed();
+        if strict_teardown && failed > 0 {
+            if let Err(e) = summary {
+                error(e.to_string());
+            }
+            bail!("{} teardown script(s) failed", failed);
+        }
+        summary
+    }
+}
+
+/// Execute a function for each file.
+///
+/// The result and duration for each file is collected in
+/// the report, when not continuing on error the first error
+/// is returned.
+fn run_files<F>(
+    files: &[(PathBuf, String)],
+    parallel: bool,
+    continue_on_error: bool,
+    results: &mut RunReport,
+    func: F,
+) -> Result<()>
+where
+    F: Fn(&PathBuf, &str) -> Result<()> + Sync,
+{
+    if parallel {
+        let outcomes: Vec<(TestCase, Result<()>)> = files
+            .par_iter()
+            .map(|(input_file, file_name)| {
+                TestCase::measure(file_name, || func(input_file, file_name))
+            })
+            .collect();
+        let mut first_error = None;
+        for (case, result) in outcomes {
+            results.push(case);
+            if first_error.is_none() {
+                first_error = result.err();
+            }
+        }
+        if let (false, Some(e)) = (continue_on_error, first_error) {
+            return Err(e);
+        }
+    } else {
+        for (input_file, file_name) in files {
+            let (case, result) =
+                TestCase::measure(file_name, || func(input_file, file_name));
+            results.push(case);
+            if !continue_on_error {
+                result?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Progress through a collection of files.
+///
+/// The counter is atomic so files processed in parallel
+/// are numbered in the order they start.
+struct Progress {
+    count: AtomicUsize,
+    total: usize,
+}
+
+impl Progress {
+    fn new(total: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            total,
+        }
+    }
+
+    /// Advance to the next file and format the step.
+    fn next(&self) -> String {
+        let index = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        format!("[{}/{}]", index, self.total)
+    }
+}
+
+/// Call a function in a thread pool limited to a number of jobs.
+///
+/// Parallel iterators use the global pool when no limit is given.
+fn with_jobs<R: Send>(
+    jobs: Option<NonZeroUsize>,
+    func: impl FnOnce() -> R + Send,
+) -> Result<R> {
+    if let Some(jobs) = jobs {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.get())
+            .build()?;
+        Ok(pool.install(func))
+    } else {
+        Ok(func())
+    }
+}
+
+/// Print a summary of file results and error on failure.
+fn summary(results: RunReport) -> Result<()> {
+    for case in &results.cases {
+        if let Some(e) = &case.failure {
+            error(format!("{} {}", case.name, e));
+        }
+    }
+
+    let passed = results.passed();
+    let failed = results.failed();
+    if failed > 0 {
+        bail!("{} passed, {} failed", passed, failed);
     }
+    success(format!("{} passed", passed));
     Ok(())
 }
 
-fn parse(input_file: &PathBuf, file_name: &str) -> Result<()> {
+fn parse(
+    input_file: &PathBuf,
+    file_name: &str,
+    include_path: &[PathBuf],
+    progress: &Progress,
+) -> Result<()> {
     tracing::debug!(path = ?input_file, "parse");
 
-    info(format!("Parse {}", file_name));
-    match ScriptFile::parse(input_file) {
+    let step = progress.next();
+    info(format!("{} Parse {}", step, file_name));
+    match ScriptFile::parse_with(input_file, include_path, |_| {}) {
         Ok(script) => {
             println!("{:#?}", script.instructions());
         }
         Err(e) => fail(e),
     }
-    success(format!("   Ok {}", file_name));
+    success(format!("{}    Ok {}", step, file_name));
     Ok(())
 }
 
+/// Settings for running files that are not interpreter options.
+#[derive(Clone, Copy)]
+struct RunSettings<'a> {
+    include_roots: &'a [PathBuf],
+    shared: Option<&'a Mutex<SharedSession>>,
+    update_golden: bool,
+}
+
 fn run(
     input_file: &PathBuf,
     file_name: &str,
-    timeout: u64,
-    echo: bool,
-    format: bool,
-    print_comments: bool,
+    progress: &Progress,
+    mut options: InterpreterOptions,
+    golden: Option<&Golden>,
+    settings: RunSettings,
 ) -> Result<()> {
-    info(format!("Run {}", file_name));
-    let script = ScriptFile::parse(input_file)?;
-    let mut options =
-        InterpreterOptions::new(timeout, echo, format, print_comments);
-    options.id = Some(file_name.to_owned());
-    script.run(options)?;
-    success(format!(" Ok {}", file_name));
+    let RunSettings {
+        include_roots,
+        shared,
+        update_golden,
+    } = settings;
+    let step = progress.next();
+    info(format!("{} Run {}", step, file_name));
+    let script = ScriptFile::parse_with(input_file, include_roots, |_| {})?;
+    let transcript = golden.map(|_| Transcript::new());
+    options.transcript = transcript.clone();
+    if let Some(session) = shared {
+        script.run_shared(&mut session.lock().unwrap(), options)?;
+    } else {
+        script.run(options)?;
+    }
+
+    if let (Some(golden), Some(transcript)) = (golden, transcript) {
+        if update_golden {
+            golden.update(&transcript.to_vec())?;
+            info(format!("Update {}", golden.path().to_string_lossy()));
+        } else {
+            golden.compare(&transcript.to_vec())?;
+        }
+    }
+    success(format!("{}  Ok {}", step, file_name));
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
+/// Temporary directory removed when dropped.
+///
+/// Ensures the directory for a streamed recording is removed
+/// when recording fails.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Settings for recording files that are not interpreter options.
+#[derive(Clone, Copy)]
+struct RecordSettings<'a> {
+    include_roots: &'a [PathBuf],
+    trim_lines: u64,
+    render: Option<RenderFormat>,
+    overwrite: bool,
+    skip_unchanged: bool,
+}
+
 fn record(
     input_file: &PathBuf,
-    output_file: &PathBuf,
     file_name: &str,
-    cinema: &CinemaOptions,
-    timeout: u64,
-    trim_lines: u64,
-    overwrite: bool,
-    echo: bool,
-    format: bool,
-    prompt: &str,
-    print_comments: bool,
+    progress: &Progress,
+    options: InterpreterOptions,
+    settings: RecordSettings,
 ) -> Result<()> {
-    info(format!("Rec {}", file_name));
-    let script = ScriptFile::parse(input_file)?;
-    let mut options = InterpreterOptions::new_recording(
-        output_file.clone(),
+    let RecordSettings {
+        include_roots,
+        trim_lines,
+        render,
         overwrite,
-        cinema.clone(),
-        timeout,
-        echo,
-        format,
-        print_comments,
-    );
-
-    options.prompt = Some(prompt.to_string());
-    options.id = Some(file_name.to_owned());
-    script.run(options)?;
-
-    if trim_lines > 0 {
-        trim_exit(output_file, trim_lines)?;
+        skip_unchanged,
+    } = settings;
+    let step = progress.next();
+    info(format!("{} Rec {}", step, file_name));
+    let Some(output_file) = options.output.clone() else {
+        bail!("recording options must have an output file");
+    };
+    let script = ScriptFile::parse_with(input_file, include_roots, |_| {})?;
+
+    let checksum_file = output_file.with_extension("cast.hash");
+    let checksum = skip_unchanged
+        .then(|| recording_checksum(&script, &options, trim_lines, render));
+    if let Some(checksum) = &checksum {
+        if output_file.exists() {
+            let previous =
+                std::fs::read_to_string(&checksum_file).unwrap_or_default();
+            if previous.trim() == checksum {
+                success(format!("{}  Ok {} unchanged", step, file_name));
+                return Ok(());
+            }
+            if !overwrite {
+                bail!(
+                    "file {} changed, use --overwrite to replace",
+                    output_file.to_string_lossy(),
+                );
+            }
+        }
     }
-    success(format!(" Ok {}", file_name));
+
+    let recording = script.record(options, trim_lines)?;
+    if let Some(format) = render {
+        if let Some(path) = render_cast(&recording.path, format)? {
+            info(format!("Render {}", path.to_string_lossy()));
+        }
+    }
+    if let Some(checksum) = checksum {
+        std::fs::write(&checksum_file, checksum)?;
+    }
+    success(format!("{}  Ok {} {}", step, file_name, recording));
     Ok(())
 }
 
-#[doc(hidden)]
-fn init_subscriber() -> Result<()> {
-    let default_log_level =
-        "anticipate=debug,anticipate_core=debug".to_owned();
-    let env_layer = tracing_subscriber::EnvFilter::new(
-        std::env::var("RUST_LOG").unwrap_or(default_log_level),
-    );
-    let fmt_layer = tracing_subscriber::fmt::layer()
-        .with_file(false)
-        .with_line_number(false)
-        .with_target(false);
-
-    tracing_subscriber::registry()
-        .with(env_layer)
-        .with(fmt_layer)
-        .try_init()?;
+/// Checksum of the script sources and the options that
+/// change the recording.
+fn recording_checksum(
+    script: &ScriptFile,
+    options: &InterpreterOptions,
+    trim_lines: u64,
+    render: Option<RenderFormat>,
+) -> String {
+    fn sources(instructions: &[Instruction<'_>], checksum: &mut Checksum) {
+        for instruction in instructions {
+            if let Instruction::Include(source) = instruction {
+                checksum.update(source.borrow_source());
+                sources(source.borrow_instructions(), checksum);
+            }
+        }
+    }
 
-    Ok(())
+    let mut checksum = Checksum::new();
+    checksum.update(script.source());
+    sources(script.instructions(), &mut checksum);
+    checksum.update(format!(
+        "{:?} {:?} {} {:?} {} {:?} {:?} {:?} {:?} {:?}",
+        options.cinema,
+        options.cinema.as_ref().map(CinemaOptions::size),
+        options.print_comments,
+        options.prompt,
+        trim_lines,
+        render,
+        options.comment_style,
+        options.pre_commands,
+        options.post_commands,
+        options.working_dir,
+    ));
+    checksum.to_hex()
 }
 
-#[doc(hidden)]
-fn trim_exit(filename: impl AsRef<Path>, trim_lines: u64) -> io::Result<()> {
-    let mut file = File::open(filename.as_ref())?;
-    let file_size = file.seek(SeekFrom::End(0))?;
-    let mut cursor = file_size;
-    let mut bytes_read = 0;
-    let mut num_lines = 0;
-
-    // Read backwards and count newlines
-    loop {
-        if cursor > 0 {
-            cursor -= 1;
-            file.seek(SeekFrom::Start(cursor))?;
+/// Maximum length of instruction arguments when tracing.
+const TRACE_WIDTH: usize = 48;
+
+/// Print a numbered line for an instruction.
+fn trace_instruction(number: usize, instruction: &Instruction<'_>) {
+    let mut line = format!("{:>4} {}", number, instruction.name());
+    if let Some(argument) = instruction.argument() {
+        let argument = argument.replace(['\r', '\n'], " ");
+        line.push(' ');
+        if argument.chars().count() > TRACE_WIDTH {
+            line.extend(argument.chars().take(TRACE_WIDTH));
+            line.push_str("...");
         } else {
-            break;
+            line.push_str(&argument);
         }
+    }
+    println!("{}", line.dimmed());
+}
 
-        let mut buf = [0; 1];
-        let byte = file.read_exact(&mut buf);
-        if byte.is_err() {
-            break;
-        }
+/// Install a SIGINT handler that cancels recordings.
+///
+/// A second interrupt terminates the program immediately.
+#[cfg(unix)]
+fn interrupt_handler() -> Result<Option<Arc<AtomicBool>>> {
+    use signal_hook::{consts::SIGINT, flag};
+    let cancel = Arc::new(AtomicBool::new(false));
+    flag::register_conditional_shutdown(SIGINT, 1, cancel.clone())?;
+    flag::register(SIGINT, cancel.clone())?;
+    Ok(Some(cancel))
+}
 
-        if &buf == b"\n" {
-            num_lines += 1;
-        }
+#[cfg(not(unix))]
+fn interrupt_handler() -> Result<Option<Arc<AtomicBool>>> {
+    Ok(None)
+}
 
-        if num_lines == trim_lines + 1 {
-            break;
-        }
+fn is_interrupted(cancel: Option<&Arc<AtomicBool>>) -> bool {
+    cancel.map(|c| c.load(Ordering::SeqCst)).unwrap_or(false)
+}
 
-        bytes_read += 1;
+#[doc(hidden)]
+fn parse_multiplier(value: &str) -> Result<f64> {
+    let multiplier: f64 = value.parse()?;
+    if !multiplier.is_finite() || multiplier <= 0.0 {
+        bail!("multiplier must be a positive number");
+    }
+    Ok(multiplier)
+}
+
+#[doc(hidden)]
+fn parse_render(value: &str) -> Result<RenderFormat> {
+    match value {
+        "svg" => Ok(RenderFormat::Svg),
+        "gif" => Ok(RenderFormat::Gif),
+        _ => bail!("render format must be svg or gif"),
     }
+}
 
-    // Truncate the file
-    if bytes_read < file_size {
-        let file = OpenOptions::new().write(true).open(filename.as_ref())?;
-        let new_len = file_size - bytes_read;
-        tracing::debug!(
-            len = %new_len,
-            file = ?filename.as_ref(),
-            "truncate",
-        );
-        file.set_len(new_len)?;
+/// Log level for the number of verbose flags.
+fn verbosity_level(verbose: u8) -> Option<Level> {
+    match verbose {
+        0 => None,
+        1 => Some(Level::DEBUG),
+        _ => Some(Level::TRACE),
     }
+}
 
+#[doc(hidden)]
+/// Initialize logging, a log level takes precedence over `RUST_LOG`.
+fn init_subscriber(
+    log_format: LogFormat,
+    log_level: Option<Level>,
+) -> Result<()> {
+    let directives = if let Some(level) = log_level {
+        format!("anticipate={}", level)
+    } else {
+        std::env::var("RUST_LOG").unwrap_or_else(|_| {
+            "anticipate=debug,anticipate_core=debug".to_owned()
+        })
+    };
+    let env_layer = tracing_subscriber::EnvFilter::new(directives);
+    let registry = tracing_subscriber::registry().with(env_layer);
+
+    match log_format {
+        LogFormat::Human => {
+            let fmt_layer = tracing_subscriber::fmt::layer()
+                .with_file(false)
+                .with_line_number(false)
+                .with_target(false);
+            registry.with(fmt_layer).try_init()?;
+        }
+        LogFormat::Json => {
+            let fmt_layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_file(false)
+                .with_line_number(false)
+                .with_target(false);
+            registry.with(fmt_layer).try_init()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Start with an empty IO log, scripts append to the file.
+fn truncate_log(path: Option<&Path>) -> Result<()> {
+    if let Some(path) = path {
+        std::fs::File::create(path)?;
+    }
+    Ok(())
+}
+
+fn check_working_dir(cwd: Option<&Path>) -> Result<()> {
+    if let Some(dir) = cwd {
+        if !dir.is_dir() {
+            bail!(
+                "working directory {} does not exist",
+                dir.to_string_lossy()
+            );
+        }
+    }
     Ok(())
 }
 
@@ -569,6 +1417,7 @@ fn check_recording_files(
     input: Vec<PathBuf>,
     output: &Path,
     overwrite: bool,
+    skip_unchanged: bool,
 ) -> Result<Vec<(PathBuf, PathBuf, String)>> {
     let mut files = Vec::new();
     for file in input {
@@ -585,7 +1434,10 @@ fn check_recording_files(
             bail!("file {} does not exist", file.to_string_lossy(),);
         }
 
-        if !overwrite && output_file.exists() {
+        // Changed recordings are detected when recording
+        let has_checksum = skip_unchanged
+            && output_file.with_extension("cast.hash").exists();
+        if !overwrite && !has_checksum && output_file.exists() {
             bail!(
                 "file {} already exists, use --overwrite to replace",
                 output_file.to_string_lossy(),
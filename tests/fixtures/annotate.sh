@@ -0,0 +1,3 @@
+# annotated comment
+echo "done"
+#$ expect done
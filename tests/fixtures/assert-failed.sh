@@ -0,0 +1,3 @@
+echo "alice@example"
+#$ expect-captures "(\w+)@(\w+)" user host
+#$ assert $user == bob
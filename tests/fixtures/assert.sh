@@ -0,0 +1,4 @@
+echo "alice@example"
+#$ expect-captures "(\w+)@(\w+)" user host
+#$ assert $user == alice
+#$ assert $host =~ ^ex[a-z]+$
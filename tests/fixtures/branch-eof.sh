@@ -0,0 +1,5 @@
+#!/bin/sh -c true
+
+#$ branch
+#$ Are you sure? [y/n] => y
+#$ end
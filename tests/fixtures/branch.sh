@@ -0,0 +1,7 @@
+#!../programs/confirm.sh
+
+#$ branch
+#$ Overwrite? => n
+#$ Are you sure? [y/n] => y
+#$ end
+#$ expect confirmed y
@@ -0,0 +1,3 @@
+echo "hello world"
+#$ sleep 10000
+#$ expect hello world
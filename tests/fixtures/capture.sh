@@ -0,0 +1,3 @@
+echo "version 1.42"
+#$ capture minor version [0-9]+\.([0-9]+)
+#$ assert $minor == 42
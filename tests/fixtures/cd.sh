@@ -0,0 +1,3 @@
+#$ cd tests/fixtures/snippets
+ls
+#$ expect snippet.sh
@@ -0,0 +1,2 @@
+echo "unclosed
+#$ wait
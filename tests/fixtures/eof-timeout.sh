@@ -0,0 +1,3 @@
+#$ quit sleep 1; exit
+echo "hello world"
+#$ expect hello world
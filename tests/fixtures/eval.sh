@@ -0,0 +1,3 @@
+#$ eval export ANTICIPATE_EVAL=eval-value
+#$ sendline printenv ANTICIPATE_EVAL
+#$ expect eval-value
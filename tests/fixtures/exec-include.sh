@@ -0,0 +1,4 @@
+#!/bin/cat target/exec-include.txt
+#$ include includes/exec.sh
+
+#$ expect included
@@ -0,0 +1,4 @@
+#!/bin/cat target/exec-setup.txt
+#$ exec sh -c "echo configured > target/exec-setup.txt"
+
+#$ expect configured
@@ -0,0 +1,4 @@
+echo "alice@example"
+#$ expect-captures "(\w+)@(\w+)" user host
+[ "$user@$host" = "alice@example" ] && echo "ok$((1 + 1))"
+#$ expect ok2
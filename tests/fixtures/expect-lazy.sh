@@ -0,0 +1,9 @@
+echo 123
+#$ lazy
+#$ expect-captures "([0-9]+)" lazy
+#$ assert $lazy == 1
+#$ wait
+#$ greedy
+echo 456
+#$ expect-captures "([0-9]+)" greedy
+#$ assert $greedy == 456
@@ -0,0 +1,2 @@
+for n in 1 2 3; do echo "line $((n * 11))"; done
+#$ expectseq "line 11" "line 22" "line 33"
@@ -0,0 +1,2 @@
+echo second
+#$ expect second
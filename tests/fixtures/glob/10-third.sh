@@ -0,0 +1,2 @@
+echo third
+#$ expect third
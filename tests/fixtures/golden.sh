@@ -0,0 +1,3 @@
+#!../programs/read-name.sh
+
+#$ expect Name:
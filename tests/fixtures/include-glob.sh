@@ -0,0 +1,2 @@
+#$ include glob/*.sh
+#$ wait
@@ -0,0 +1 @@
+#$ exec sh -c "echo included > target/exec-include.txt"
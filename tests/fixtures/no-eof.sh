@@ -0,0 +1,4 @@
+echo bye
+#$ expect bye
+exit
+#$ waitexit
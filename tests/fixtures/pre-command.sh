@@ -0,0 +1,2 @@
+echo "value: $ANTICIPATE_PRE_COMMAND"
+#$ expect value: bar
@@ -0,0 +1,7 @@
+#!../programs/counter-prompt.sh
+
+#$ wait
+first
+#$ wait
+second
+#$ wait
@@ -0,0 +1,2 @@
+#!../programs/quiet.sh
+#$ quiet 1000
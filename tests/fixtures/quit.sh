@@ -0,0 +1,3 @@
+#$ quit exit
+echo "hello world"
+#$ expect hello world
@@ -0,0 +1,5 @@
+echo "captured"
+#$ readline command
+#$ readline output
+echo "value: $output"
+#$ expect value: captured
@@ -0,0 +1 @@
+#$ require tests/fixtures/bin/fake-tool >= 1.10.0
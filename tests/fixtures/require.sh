@@ -0,0 +1,3 @@
+#$ require tests/fixtures/bin/fake-tool >= 1.2
+echo "ready"
+#$ expect ready
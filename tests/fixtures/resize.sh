@@ -0,0 +1,3 @@
+#$ resize 100 40
+stty size
+#$ expect 40 100
@@ -0,0 +1,3 @@
+#$ sendline echo "screen""shot"
+#$ expect screenshot
+#$ screenshot ../../target/screenshot.txt
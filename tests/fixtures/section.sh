@@ -0,0 +1,3 @@
+#@ section Greeting
+echo "hello"
+#$ expect hello
@@ -0,0 +1,5 @@
+#!../programs/arrow-key.sh
+
+#$ expect ready
+#$ sendbytes 1b 5b 41
+#$ expect pressed up
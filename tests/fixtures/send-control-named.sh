@@ -0,0 +1,6 @@
+cat
+#$ sendcontrol ^C
+#$ wait
+cat
+#$ sendcontrol EOT
+#$ wait
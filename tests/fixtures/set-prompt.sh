@@ -0,0 +1,9 @@
+#!sh
+#$ setprompt sub>
+PS1='sub''> ' bash --norc --noprofile
+#$ expectprompt
+echo inside
+#$ expect inside
+#$ setprompt ➜
+exit
+#$ expectprompt
@@ -0,0 +1,2 @@
+printenv SHARED_VALUE
+#$ expect shared-42
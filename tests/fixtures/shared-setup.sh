@@ -0,0 +1,2 @@
+export SHARED_VALUE="shared-$((6 * 7))"
+#$ wait
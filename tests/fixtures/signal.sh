@@ -0,0 +1,4 @@
+#!/bin/sleep 100
+
+#$ signal SIGTERM
+#$ expectexit 143
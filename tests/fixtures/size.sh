@@ -0,0 +1,2 @@
+stty size
+#$ expect 40 132
@@ -0,0 +1,3 @@
+#!../programs/slow.sh
+
+#$ expect ready
@@ -0,0 +1,2 @@
+#$ sleep 10000
+echo "unreachable"
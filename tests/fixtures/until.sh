@@ -0,0 +1,3 @@
+#$ sendline echo one
+#$ expect one
+#$ expect never
@@ -0,0 +1,6 @@
+#!../programs/python-prompt.sh
+#$ waitany
+for i in range(3):
+#$ waitany
+print(i)
+#$ waitany
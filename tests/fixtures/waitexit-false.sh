@@ -0,0 +1,4 @@
+#!/bin/sh -c false
+
+#$ waitexit
+#$ expectexit 1
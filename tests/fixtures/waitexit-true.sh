@@ -0,0 +1,4 @@
+#!/bin/sh -c true
+
+#$ waitexit
+#$ expectexit 0
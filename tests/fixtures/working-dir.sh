@@ -0,0 +1,2 @@
+ls
+#$ expect snippet.sh
@@ -1,5 +1,17 @@
-use anticipate_runner::{InterpreterOptions, ScriptFile};
+use anticipate_runner::{
+    CinemaOptions, CommentStyle, Error, Golden, Instruction,
+    InterpreterOptions, LogWriter, PromptPattern, ScriptFile, SharedSession,
+    Transcript,
+};
 use anyhow::Result;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 #[test]
 fn interpret_echo() -> Result<()> {
@@ -8,6 +20,444 @@ fn interpret_echo() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn interpret_transform() -> Result<()> {
+    let file = ScriptFile::parse_with("tests/fixtures/echo.sh", &[], |i| {
+        i.insert(
+            0,
+            Instruction::Sleep {
+                base: 500,
+                jitter: 0,
+            },
+        );
+    })?;
+    assert!(matches!(
+        file.instructions().first(),
+        Some(Instruction::Sleep {
+            base: 500,
+            jitter: 0
+        })
+    ));
+
+    let start = Instant::now();
+    file.run(Default::default())?;
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    Ok(())
+}
+
+#[test]
+fn interpret_log_writer() -> Result<()> {
+    struct BufferLogWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl LogWriter for BufferLogWriter {
+        fn log_read(&mut self, data: &[u8]) {
+            self.0.lock().unwrap().extend_from_slice(data);
+        }
+
+        fn log_write(&mut self, _data: &[u8]) {}
+    }
+
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let file = ScriptFile::parse("tests/fixtures/echo.sh")?;
+    let options = InterpreterOptions {
+        log_writer: Some(Box::new(BufferLogWriter(buffer.clone()))),
+        ..Default::default()
+    };
+    file.run(options)?;
+
+    let output = String::from_utf8(buffer.lock().unwrap().clone())?;
+    assert!(output.contains("hello world"));
+    Ok(())
+}
+
+#[test]
+fn interpret_on_instruction() -> Result<()> {
+    let trace = Arc::new(Mutex::new(Vec::new()));
+    let file = ScriptFile::parse("tests/fixtures/echo.sh")?;
+    let hook = trace.clone();
+    let options = InterpreterOptions {
+        on_instruction: Some(Box::new(move |number, instruction| {
+            hook.lock().unwrap().push((
+                number,
+                instruction.name(),
+                instruction.argument().map(|a| a.into_owned()),
+            ));
+        })),
+        ..Default::default()
+    };
+    file.run(options)?;
+
+    let trace = trace.lock().unwrap();
+    assert_eq!(
+        vec![
+            (1, "sendline", Some(r#"echo "hello world""#.to_owned())),
+            (2, "expect", Some("hello world".to_owned())),
+        ],
+        *trace
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_no_eof() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/no-eof.sh")?;
+    let options = InterpreterOptions {
+        send_eof: false,
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_working_dir() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/working-dir.sh")?;
+    let options = InterpreterOptions {
+        working_dir: Some(PathBuf::from("tests/fixtures/snippets")),
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_size() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/size.sh")?;
+    let options = InterpreterOptions {
+        size: Some((132, 40)),
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_cd() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/cd.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_quiet() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/quiet.sh")?;
+    // Output stops after 300ms then quiet for 1000ms
+    let started = Instant::now();
+    file.run(Default::default())?;
+    assert!(started.elapsed() >= Duration::from_millis(1300));
+
+    let options = InterpreterOptions::builder()
+        .total_timeout(Duration::from_millis(500))
+        .build();
+    let result = file.run(options);
+    assert!(matches!(result, Err(Error::TotalTimeout(_))));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_log_file() -> Result<()> {
+    let path = "target/log-file.txt";
+    let _ = std::fs::remove_file(path);
+    let file = ScriptFile::parse("tests/fixtures/echo.sh")?;
+    let options = InterpreterOptions::builder()
+        .format(true)
+        .log_file(path)
+        .build();
+    file.run(options)?;
+    let contents = std::fs::read_to_string(path)?;
+    assert!(contents.lines().any(|line| line.starts_with("read: ")));
+    assert!(contents.lines().any(|line| line.starts_with("write: ")));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_attach_fd() -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::{os::fd::OwnedFd, os::unix::net::UnixStream};
+
+    let (local, remote) = UnixStream::pair()?;
+    // Answer commands like a shell until end of transmission
+    let shell =
+        std::thread::spawn(move || -> std::io::Result<Vec<String>> {
+            let mut writer = remote.try_clone()?;
+            let mut lines = Vec::new();
+            for line in BufReader::new(remote).split(b'\n') {
+                let line = String::from_utf8_lossy(&line?).into_owned();
+                if line.contains('\x04') {
+                    break;
+                }
+                if let Some(text) = line.strip_prefix("echo ") {
+                    writer.write_all(format!("{}\n", text).as_bytes())?;
+                }
+                lines.push(line);
+            }
+            Ok(lines)
+        });
+
+    let file = ScriptFile::parse("tests/fixtures/attach.sh")?;
+    let options = InterpreterOptions::builder()
+        .attach_fd(OwnedFd::from(local))
+        .build();
+    file.run(options)?;
+    assert_eq!(vec!["echo hello".to_owned()], shell.join().unwrap()?);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_screenshot() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/screenshot.sh")?;
+    file.run(Default::default())?;
+    let contents = std::fs::read_to_string("target/screenshot.txt")?;
+    assert!(contents.lines().any(|line| line == "screenshot"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_stop_after() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/until.sh")?;
+    let options = InterpreterOptions::builder()
+        .timeout(2000)
+        .stop_after("expect one")
+        .build();
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_eval() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/eval.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_expect_sequence() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/expectseq.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_shared_session() -> Result<()> {
+    let setup = ScriptFile::parse("tests/fixtures/shared-setup.sh")?;
+    let main = ScriptFile::parse("tests/fixtures/shared-main.sh")?;
+    let mut session = SharedSession::spawn(Default::default())?;
+    setup.run_shared(&mut session, Default::default())?;
+    main.run_shared(&mut session, Default::default())?;
+    session.finish()?;
+
+    // Variable is not set in a new shell
+    let options = InterpreterOptions::builder().timeout(500).build();
+    assert!(main.run(options).is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_set_prompt() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/set-prompt.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_expect_lazy() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/expect-lazy.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_expect_captures() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/expect-captures.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_capture() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/capture.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_send_bytes() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/send-bytes.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_send_control_named() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/send-control-named.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[test]
+fn interpret_options_builder() {
+    let options = InterpreterOptions::builder()
+        .timeout(2000)
+        .echo(true)
+        .prompt("$ ")
+        .size(132, 40)
+        .build();
+    assert_eq!(Some(2000), options.timeout);
+    assert!(options.echo);
+    assert!(
+        matches!(options.prompt, Some(PromptPattern::Literal(ref p)) if p == "$ ")
+    );
+    assert_eq!(Some((132, 40)), options.size);
+    assert!(options.send_eof);
+
+    let options = InterpreterOptions::new_recording(
+        "target/builder.cast",
+        true,
+        Default::default(),
+        5000,
+        false,
+        false,
+        false,
+    );
+    assert_eq!(
+        r#"asciinema rec "target/builder.cast" --overwrite --rows=24 --cols=80"#,
+        options.command
+    );
+    assert!(options.cinema.is_some());
+    assert_eq!(Some(5000), options.timeout);
+}
+
+#[test]
+fn interpret_shell_command() {
+    let cinema = CinemaOptions {
+        shell_program: "bash".to_owned(),
+        shell_args: vec![
+            "--norc".to_owned(),
+            "-c".to_owned(),
+            "echo 'hello world'".to_owned(),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(
+        r#"bash --norc -c 'echo '\''hello world'\'''"#,
+        cinema.shell_command()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_assert() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/assert.sh")?;
+    file.run(Default::default())?;
+
+    let file = ScriptFile::parse("tests/fixtures/assert-failed.sh")?;
+    let result = file.run(Default::default());
+    if let Err(Error::AssertionFailed { expected, actual }) = result {
+        assert_eq!("bob", expected);
+        assert_eq!("alice", actual);
+    } else {
+        panic!("expected assertion failed error");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_waitexit() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/waitexit-true.sh")?;
+    file.run(Default::default())?;
+    let file = ScriptFile::parse("tests/fixtures/waitexit-false.sh")?;
+    file.run(Default::default())?;
+
+    let file = ScriptFile::parse_str(
+        "#!/bin/sh -c false\n#$ expectexit 0\n",
+        "tests/fixtures/waitexit-false.sh",
+    )?;
+    let result = file.run(Default::default());
+    assert!(matches!(
+        result,
+        Err(Error::ExitStatus {
+            expected: 0,
+            actual: 1,
+            quit: None,
+        })
+    ));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_exec() -> Result<()> {
+    let _ = std::fs::remove_file("target/exec-setup.txt");
+    let file = ScriptFile::parse("tests/fixtures/exec.sh")?;
+    file.run(Default::default())?;
+
+    let _ = std::fs::remove_file("target/exec-include.txt");
+    let file = ScriptFile::parse("tests/fixtures/exec-include.sh")?;
+    file.run(Default::default())?;
+
+    let file = ScriptFile::parse_str("#$ exec false\necho bar\n", "exec.sh")?;
+    let result = file.run(Default::default());
+    assert!(matches!(result, Err(Error::ExecFailed(_, _))));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_exec_timeout() -> Result<()> {
+    let file =
+        ScriptFile::parse_str("#$ exec sleep 10\necho bar\n", "exec.sh")?;
+    let started = Instant::now();
+    let result = file.run(InterpreterOptions::new(100, false, false, false));
+    assert!(matches!(result, Err(Error::ExecTimeout(_, _))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    let options = InterpreterOptions::builder()
+        .cancel(Arc::new(AtomicBool::new(true)))
+        .build();
+    let result = file.run(options);
+    assert!(matches!(result, Err(Error::Cancelled)));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_signal() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/signal.sh")?;
+    file.run(Default::default())?;
+
+    let file = ScriptFile::parse_str("#$ signal SIGBOGUS\n", "signal.sh")?;
+    let result = file.run(Default::default());
+    assert!(matches!(result, Err(Error::InvalidSignal(_))));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_resize() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/resize.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
 #[test]
 fn interpret_escaped_newline() -> Result<()> {
     let file = ScriptFile::parse("tests/fixtures/escaped-newline.sh")?;
@@ -48,3 +498,238 @@ fn interpret_include() -> Result<()> {
     file.run(Default::default())?;
     Ok(())
 }
+
+#[cfg(unix)]
+#[test]
+fn interpret_golden() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/golden.sh")?;
+    let transcript = Transcript::new();
+    let options = InterpreterOptions {
+        transcript: Some(transcript.clone()),
+        ..Default::default()
+    };
+    file.run(options)?;
+
+    let golden = Golden::new("tests/fixtures/golden.txt", true);
+    golden.compare(&transcript.to_vec())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_golden_mismatch() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/golden.sh")?;
+    let transcript = Transcript::new();
+    let options = InterpreterOptions {
+        transcript: Some(transcript.clone()),
+        ..Default::default()
+    };
+    file.run(options)?;
+
+    let golden = Golden::new("tests/fixtures/golden-mismatch.txt", true);
+    let result = golden.compare(&transcript.to_vec());
+    if let Err(Error::GoldenMismatch(_, diff)) = result {
+        assert_eq!("-Name: Mock\n+Name: \n", diff);
+    } else {
+        panic!("expected golden mismatch error");
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_quit() -> Result<()> {
+    // The shell is waited on and must exit cleanly
+    let file = ScriptFile::parse("tests/fixtures/quit.sh")?;
+    file.run(Default::default())?;
+
+    let file =
+        ScriptFile::parse_str("#$ quit exit 3\n", "tests/fixtures/quit.sh")?;
+    let result = file.run(Default::default());
+    assert!(matches!(
+        result,
+        Err(Error::ExitStatus {
+            expected: 0,
+            actual: 3,
+            quit: Some(quit),
+        }) if quit == "exit 3"
+    ));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_eof_timeout() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/eof-timeout.sh")?;
+    let options = InterpreterOptions::builder().timeout(500).build();
+    assert!(file.run(options).is_err());
+
+    let options = InterpreterOptions::builder()
+        .timeout(500)
+        .eof_timeout(Duration::from_millis(5000))
+        .build();
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_unexpected_continuation() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/continuation.sh")?;
+    let options = InterpreterOptions::builder().timeout(500).build();
+    let result = file.run(options);
+    assert!(matches!(result, Err(Error::UnexpectedContinuation(_))));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_require() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/require.sh")?;
+    file.run(Default::default())?;
+
+    let file = ScriptFile::parse("tests/fixtures/require-newer.sh")?;
+    let result = file.run(Default::default());
+    if let Err(Error::VersionMismatch { found, .. }) = result {
+        assert_eq!("1.4.2", found);
+    } else {
+        panic!("expected version mismatch error");
+    }
+    Ok(())
+}
+
+#[test]
+fn interpret_cancel() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/cancel.sh")?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    let options = InterpreterOptions {
+        cancel: Some(cancel.clone()),
+        ..Default::default()
+    };
+
+    let handle = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(250));
+        cancel.store(true, Ordering::SeqCst);
+    });
+
+    let start = Instant::now();
+    let result = file.run(options);
+    handle.join().unwrap();
+
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert!(start.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_timeout_multiplier() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/timeout-multiplier.sh")?;
+    let options = InterpreterOptions {
+        timeout: Some(500),
+        ..Default::default()
+    };
+    let result = file.run(options);
+    assert!(matches!(result, Err(Error::Expect(_))));
+
+    let options = InterpreterOptions {
+        timeout: Some(500),
+        timeout_multiplier: 4.0,
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_total_timeout() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/total-timeout.sh")?;
+    let options = InterpreterOptions {
+        total_timeout: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+    let started = Instant::now();
+    let result = file.run(options);
+    assert!(matches!(result, Err(Error::TotalTimeout(_))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_branch() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/branch.sh")?;
+    file.run(Default::default())?;
+
+    let file = ScriptFile::parse("tests/fixtures/branch-eof.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_annotate_comments() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/annotate.sh")?;
+    let transcript = Transcript::new();
+    let options = InterpreterOptions {
+        print_comments: true,
+        comment_style: CommentStyle::Annotate,
+        transcript: Some(transcript.clone()),
+        ..Default::default()
+    };
+    file.run(options)?;
+
+    // Comment is typed but the line is cleared before enter
+    let output = String::from_utf8_lossy(&transcript.to_vec()).into_owned();
+    assert!(output.contains("# annotated comment"));
+    assert!(!output.contains("# annotated comment\r\n"));
+    assert!(output.contains("done"));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_prompt_regex() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/prompt-regex.sh")?;
+    let options = InterpreterOptions {
+        prompt: Some(PromptPattern::Regex(r"\[[0-9]+\]> ".to_owned())),
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_waitany() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/waitany.sh")?;
+    let options = InterpreterOptions {
+        prompt: Some(PromptPattern::Literal(">>> ".to_owned())),
+        prompts: vec!["... ".to_owned()],
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_pre_command() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/pre-command.sh")?;
+    let options = InterpreterOptions {
+        pre_commands: vec!["export ANTICIPATE_PRE_COMMAND=bar".to_owned()],
+        post_commands: vec!["unset ANTICIPATE_PRE_COMMAND".to_owned()],
+        ..Default::default()
+    };
+    file.run(options)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn interpret_readline_into() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/readline-into.sh")?;
+    file.run(Default::default())?;
+    Ok(())
+}
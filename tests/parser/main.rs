@@ -1,5 +1,8 @@
-use anticipate_runner::{Error, Instruction, ScriptFile, ScriptParser};
+use anticipate_runner::{
+    Error, Instruction, LineEnding, Location, ScriptFile, ScriptParser,
+};
 use anyhow::Result;
+use std::path::PathBuf;
 
 #[test]
 fn parse_pragma() -> Result<()> {
@@ -22,6 +25,106 @@ fn parse_sendline() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_sendline_escaped() -> Result<()> {
+    let source = "\\#$ hello\n  \\#$\n#$ sendline \\#$ raw";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(3, instructions.len());
+    let lines = instructions
+        .iter()
+        .map(|i| match i {
+            Instruction::SendLine(line) => *line,
+            _ => panic!("expected sendline instruction"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(vec!["#$ hello", "#$", "\\#$ raw"], lines);
+    Ok(())
+}
+
+#[test]
+fn parse_interact() -> Result<()> {
+    let source = "#$ interact\n#$ interact ^X\n";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Interact(None))
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::Interact(Some("^X")))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_screenshot() -> Result<()> {
+    let source = "#$ screenshot target/screen.txt";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Screenshot("target/screen.txt"))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_eval() -> Result<()> {
+    let source = "#$ eval export FOO=bar";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Eval("export FOO=bar"))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_expect_sequence() -> Result<()> {
+    let source = "#$ expectseq one \"two words\" 'three'";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::ExpectSequence(patterns)) = instructions.first()
+    {
+        assert_eq!(&["one", "two words", "three"], patterns.as_slice());
+    } else {
+        panic!("expected expectseq instruction");
+    }
+
+    let result = ScriptParser::parse("#$ expectseq \"unterminated");
+    assert!(matches!(result, Err(Error::InvalidSequence(_, _))));
+    Ok(())
+}
+
+#[test]
+fn parse_require() -> Result<()> {
+    let source = "#$ require git >= 2.30.1\n#$ require bash\n";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Require {
+            program: "git",
+            version: Some("2.30.1")
+        })
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::Require {
+            program: "bash",
+            version: None
+        })
+    ));
+
+    for source in ["#$ require git > 2", "#$ require git >= v2"] {
+        let result = ScriptParser::parse(source);
+        assert!(matches!(result, Err(Error::InvalidRequirement(_, _))));
+    }
+    Ok(())
+}
+
 #[test]
 fn parse_readline() -> Result<()> {
     let source = "#$ readline";
@@ -31,6 +134,20 @@ fn parse_readline() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_readline_into() -> Result<()> {
+    let source = "#$ readline\n#$ readline foo";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(instructions.first(), Some(Instruction::ReadLine)));
+    if let Some(Instruction::ReadLineInto(name)) = instructions.get(1) {
+        assert_eq!("foo", name);
+    } else {
+        panic!("expected readline into instruction");
+    }
+    Ok(())
+}
+
 #[test]
 fn parse_sendline_raw() -> Result<()> {
     let source = "foo";
@@ -64,6 +181,39 @@ fn parse_expect() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_expect_quoted() -> Result<()> {
+    let source = r#"#$ expect "foo bar""#;
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Expect(pattern)) if pattern == "foo bar"
+    ));
+
+    let source = r#"#$ expect 'done\r\n\t"quoted"\''"#;
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Expect(pattern))
+            if pattern == "done\r\n\t\"quoted\"'"
+    ));
+
+    let source = r#"#$ expect "foo\d""#;
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Expect(pattern)) if pattern == "foo\\d"
+    ));
+
+    let source = r#"#$ expect "foo bar"#;
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Expect(pattern)) if pattern == "\"foo bar"
+    ));
+    Ok(())
+}
+
 #[test]
 fn parse_regex() -> Result<()> {
     let source = "#$ regex [0-9]";
@@ -73,6 +223,138 @@ fn parse_regex() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_expect_captures() -> Result<()> {
+    let source = r#"#$ expect-captures "(\w+)@(\w+)" user host"#;
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::ExpectCaptures(pattern, names)) =
+        instructions.first()
+    {
+        assert_eq!(r"(\w+)@(\w+)", *pattern);
+        assert_eq!(&["user", "host"], names.as_slice());
+    } else {
+        panic!("expected expect captures instruction");
+    }
+
+    let source = "#$ expect-captures ([0-9]+)";
+    let result = ScriptParser::parse(source);
+    assert!(matches!(
+        result,
+        Err(Error::InvalidCaptures(_, Location { line: 1, .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_capture() -> Result<()> {
+    let source = "#$ capture version v([0-9]+) build";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::Capture { name, pattern }) = instructions.first()
+    {
+        assert_eq!("version", *name);
+        assert_eq!("v([0-9]+) build", *pattern);
+    } else {
+        panic!("expected capture instruction");
+    }
+
+    let source = "echo foo\n#$ capture version";
+    let result = ScriptParser::parse(source);
+    assert!(matches!(
+        result,
+        Err(Error::InvalidCapture(_, Location { line: 2, .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_assert() -> Result<()> {
+    let source = "#$ assert $name == foo";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Assert("$name == foo"))
+    ));
+
+    let source = "echo foo\n#$ assert $name != foo";
+    let result = ScriptParser::parse(source);
+    assert!(matches!(
+        result,
+        Err(Error::InvalidAssertion(_, Location { line: 2, .. }))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_waitexit() -> Result<()> {
+    let source = "#$ waitexit\n#$ expectexit 1";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(instructions.first(), Some(Instruction::WaitExit)));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::ExpectExit(1))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_resize() -> Result<()> {
+    let source = "#$ resize 100 40";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Resize {
+            cols: 100,
+            rows: 40
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_branch() -> Result<()> {
+    let source = r#"#$ branch
+#$ Are you sure? [y/n] => y
+#$ Overwrite? => n
+#$ end
+echo done"#;
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Branch(arms))
+            if arms == &[("Are you sure? [y/n]", "y"), ("Overwrite?", "n")]
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::SendLine("echo done"))
+    ));
+
+    let source = "#$ branch\n#$ Are you sure?\n#$ end";
+    let result = ScriptParser::parse(source);
+    assert!(matches!(result, Err(Error::InvalidBranch(_, _))));
+
+    let source = "#$ branch\n#$ Are you sure? => y";
+    let result = ScriptParser::parse(source);
+    assert!(matches!(result, Err(Error::UnterminatedBranch(_))));
+    Ok(())
+}
+
+#[test]
+fn parse_lazy_greedy() -> Result<()> {
+    let source = "#$ lazy\n#$ regex [0-9]+\n#$ greedy";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(3, instructions.len());
+    assert!(matches!(instructions.first(), Some(Instruction::Lazy)));
+    assert!(matches!(instructions.get(1), Some(Instruction::Regex(_))));
+    assert!(matches!(instructions.get(2), Some(Instruction::Greedy)));
+    Ok(())
+}
+
 #[test]
 fn parse_sendcontrol() -> Result<()> {
     let source = "#$ sendcontrol c";
@@ -85,12 +367,107 @@ fn parse_sendcontrol() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_signal() -> Result<()> {
+    let source = "#$ signal SIGTERM";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Signal(name)) if name == "SIGTERM"
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_sendcontrol_named() -> Result<()> {
+    let source = "#$ sendcontrol ^C\n#$ sendcontrol EOT";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::SendControl("^C"))
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::SendControl("EOT"))
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_quiet() -> Result<()> {
+    let source = "#$ quiet 500";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Quiet(500))
+    ));
+    Ok(())
+}
+
 #[test]
 fn parse_sleep() -> Result<()> {
     let source = "#$ sleep 500";
     let instructions = ScriptParser::parse(source)?;
     assert_eq!(1, instructions.len());
-    assert!(matches!(instructions.first(), Some(Instruction::Sleep(_))));
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Sleep {
+            base: 500,
+            jitter: 0
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_sleep_units() -> Result<()> {
+    let source = "#$ sleep 2s\n#$ sleep 500ms\n#$ sleep 1m\n#$ sleep 250\n";
+    let instructions = ScriptParser::parse(source)?;
+    let delays: Vec<u64> = instructions
+        .iter()
+        .filter_map(|i| match i {
+            Instruction::Sleep { base, .. } => Some(*base),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec![2000, 500, 60000, 250], delays);
+
+    let result = ScriptParser::parse("#$ sleep abc");
+    assert!(result.is_err());
+    let result = ScriptParser::parse("#$ sleep 2h");
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[test]
+fn parse_sleep_jitter() -> Result<()> {
+    let source = "#$ sleep 500 ±100\n#$ sleep 2s+-250ms\n";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Sleep {
+            base: 500,
+            jitter: 100
+        })
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::Sleep {
+            base: 2000,
+            jitter: 250
+        })
+    ));
+    assert_eq!(
+        Some("500 ±100"),
+        instructions.first().and_then(|i| i.argument()).as_deref()
+    );
+
+    let result = ScriptParser::parse("#$ sleep 500 ±1h");
+    assert!(result.is_err());
     Ok(())
 }
 
@@ -106,6 +483,27 @@ fn parse_comment() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_section() -> Result<()> {
+    let source = "#@ section Build steps\n# comment\n#@ note";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(3, instructions.len());
+    if let Some(Instruction::Section(name)) = instructions.first() {
+        assert_eq!("Build steps", name);
+    } else {
+        panic!("expected section instruction");
+    }
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::Comment("# comment"))
+    ));
+    assert!(matches!(
+        instructions.get(2),
+        Some(Instruction::Comment("#@ note"))
+    ));
+    Ok(())
+}
+
 #[test]
 fn parse_send() -> Result<()> {
     let source = "#$ send echo";
@@ -115,6 +513,29 @@ fn parse_send() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_send_bytes() -> Result<()> {
+    let source = "#$ sendbytes 1b5b41";
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::SendBytes(bytes)) if bytes == b"\x1b[A"
+    ));
+
+    let source = "#$ sendbytes 00 FF";
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::SendBytes(bytes)) if bytes == &[0x00, 0xff]
+    ));
+
+    for source in ["#$ sendbytes 1b5", "#$ sendbytes zz", "#$ sendbytes +f"] {
+        let result = ScriptParser::parse(source);
+        assert!(matches!(result, Err(Error::InvalidHex(_, _))));
+    }
+    Ok(())
+}
+
 #[test]
 fn parse_flush() -> Result<()> {
     let source = "#$ flush";
@@ -133,6 +554,37 @@ fn parse_wait() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_waitany() -> Result<()> {
+    let source = "#$ waitany";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(instructions.first(), Some(Instruction::WaitAny)));
+    Ok(())
+}
+
+#[test]
+fn parse_expect_prompt() -> Result<()> {
+    let source = "#$ expectprompt";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    assert!(matches!(instructions.first(), Some(Instruction::Wait)));
+    Ok(())
+}
+
+#[test]
+fn parse_set_prompt() -> Result<()> {
+    let source = "#$ setprompt sub>";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::SetPrompt(prompt)) = instructions.first() {
+        assert_eq!("sub>", *prompt);
+    } else {
+        panic!("expected setprompt instruction");
+    }
+    Ok(())
+}
+
 #[test]
 fn parse_clear() -> Result<()> {
     let source = "#$ clear";
@@ -142,6 +594,32 @@ fn parse_clear() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_cd() -> Result<()> {
+    let source = "#$ cd target/$NAME";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::Cd(path)) = instructions.first() {
+        assert_eq!("target/$NAME", *path);
+    } else {
+        panic!("expected cd instruction");
+    }
+    Ok(())
+}
+
+#[test]
+fn parse_quit() -> Result<()> {
+    let source = "#$ quit exit";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::Quit(command)) = instructions.first() {
+        assert_eq!("exit", *command);
+    } else {
+        panic!("expected quit instruction");
+    }
+    Ok(())
+}
+
 #[test]
 fn parse_include() -> Result<()> {
     let file = "tests/fixtures/include.sh";
@@ -175,6 +653,26 @@ fn parse_include() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_str_include() -> Result<()> {
+    let source = "#$ include includes/shared.sh\n#$ wait\n";
+    let file = ScriptFile::parse_str(source, "tests/fixtures/inline.sh")?;
+    assert_eq!(source, file.source());
+    assert_eq!(&PathBuf::from("tests/fixtures/inline.sh"), file.path());
+    let instructions = file.instructions();
+    assert_eq!(2, instructions.len());
+    if let Some(Instruction::Include(source)) = instructions.first() {
+        assert!(matches!(
+            source.borrow_instructions().first(),
+            Some(Instruction::SendLine("echo hi"))
+        ));
+    } else {
+        panic!("expected include instruction");
+    }
+    assert!(matches!(instructions.get(1), Some(Instruction::Wait)));
+    Ok(())
+}
+
 #[test]
 fn parse_include_many() -> Result<()> {
     let file = "tests/fixtures/include-many.sh";
@@ -192,6 +690,116 @@ fn parse_include_many() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_include_dependencies() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/include-many.sh")?;
+    assert_eq!(
+        &[PathBuf::from("tests/fixtures/includes/signin.sh")
+            .canonicalize()?],
+        file.dependencies()
+    );
+
+    let file = ScriptFile::parse("tests/fixtures/echo.sh")?;
+    assert!(file.dependencies().is_empty());
+    Ok(())
+}
+
+#[test]
+fn parse_include_glob() -> Result<()> {
+    let file = ScriptFile::parse("tests/fixtures/include-glob.sh")?;
+    let expected = ["01-first.sh", "02-second.sh", "10-third.sh"]
+        .iter()
+        .map(|name| PathBuf::from("tests/fixtures/glob").join(name))
+        .map(|path| path.canonicalize())
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(expected.as_slice(), file.dependencies());
+
+    let mut it = file.instructions().iter();
+    for text in ["echo first", "echo second", "echo third"] {
+        if let Some(Instruction::Include(source)) = it.next() {
+            assert!(matches!(
+                source.borrow_instructions().first(),
+                Some(Instruction::SendLine(line)) if *line == text
+            ));
+        } else {
+            panic!("expected include instruction");
+        }
+    }
+    assert!(matches!(it.next(), Some(Instruction::Wait)));
+
+    let source = "#$ include glob/missing-*.sh\n";
+    let result = ScriptParser::parse_file(
+        source,
+        "tests/fixtures/include-glob.sh",
+        &[],
+    );
+    assert!(matches!(result, Err(Error::Include(_, _, _))));
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn parse_include_unreadable() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Restore permissions and remove the directory on failure
+    struct Cleanup(PathBuf);
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            let _ = std::fs::set_permissions(
+                self.0.join("secret.sh"),
+                std::fs::Permissions::from_mode(0o644),
+            );
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    let dir = std::env::temp_dir()
+        .join(format!("anticipate-unreadable-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let _cleanup = Cleanup(dir.clone());
+    let main = dir.join("main.sh");
+    let secret = dir.join("secret.sh");
+    std::fs::write(&main, "#$ include secret.sh\n")?;
+    std::fs::write(&secret, "echo secret\n")?;
+    std::fs::set_permissions(
+        &secret,
+        std::fs::Permissions::from_mode(0o000),
+    )?;
+
+    // Permissions are not enforced for the root user
+    if std::fs::read(&secret).is_err() {
+        let result = ScriptFile::parse(&main);
+        if let Err(Error::Read(e, path)) = result {
+            assert_eq!(std::io::ErrorKind::PermissionDenied, e.kind());
+            assert!(path.ends_with("secret.sh"));
+        } else {
+            panic!("expected read error");
+        }
+    }
+
+    let result = ScriptFile::parse(dir.join("missing.sh"));
+    assert!(matches!(result, Err(Error::Read(_, _))));
+    Ok(())
+}
+
+#[test]
+fn parse_include_flat() -> Result<()> {
+    let file = "tests/fixtures/include-many.sh";
+    let file = ScriptFile::parse(file)?;
+    let instructions = file.instructions_flat();
+    assert_eq!(10, instructions.len());
+    assert!(!instructions
+        .iter()
+        .any(|i| matches!(i, Instruction::Include(_))));
+    assert!(matches!(instructions.get(2), Some(Instruction::Regex(_))));
+    assert!(matches!(
+        instructions.get(3),
+        Some(Instruction::SendLine("$ACCOUNT_PASSWORD"))
+    ));
+    Ok(())
+}
+
 #[test]
 fn parse_comment_leading_whitespace() -> Result<()> {
     let source = r#"    # comment with leading whitespace"#;
@@ -203,6 +811,49 @@ fn parse_comment_leading_whitespace() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_instruction_leading_whitespace() -> Result<()> {
+    let source = "\t#$ sendline echo foo\n  #$ expect foo\n\t #$ sleep 50";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(3, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::SendLine("echo foo"))
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::Expect(pattern)) if pattern == "foo"
+    ));
+    assert!(matches!(
+        instructions.get(2),
+        Some(Instruction::Sleep { base: 50, .. })
+    ));
+
+    let source = "  #!/bin/sh";
+    let instructions = ScriptParser::parse(source)?;
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::Pragma(cmd)) if cmd == "/bin/sh"
+    ));
+    Ok(())
+}
+
+#[test]
+fn parse_text_leading_whitespace() -> Result<()> {
+    let source = "\techo foo\n    echo bar";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::SendLine("\techo foo"))
+    ));
+    assert!(matches!(
+        instructions.get(1),
+        Some(Instruction::SendLine("    echo bar"))
+    ));
+    Ok(())
+}
+
 #[test]
 fn parse_multi_whitespace() -> Result<()> {
     let source = r#"exe cmd      -n "$FILE_NAME"  "$FILE_INPUT""#;
@@ -266,14 +917,110 @@ fn parse_pragma_first_err() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn parse_exec() -> Result<()> {
+    let source = r#"#!sh
+# setup
+#$ exec mkdir -p target/data
+#$ exec touch target/data/file.txt
+echo ready
+"#;
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(5, instructions.len());
+    assert!(matches!(
+        instructions.get(2),
+        Some(Instruction::Exec("mkdir -p target/data"))
+    ));
+    assert!(matches!(
+        instructions.get(3),
+        Some(Instruction::Exec("touch target/data/file.txt"))
+    ));
+
+    let source = "echo ready\n#$ exec touch file.txt\n";
+    let result = ScriptParser::parse(source);
+    assert!(matches!(result, Err(Error::ExecFirst(_))));
+    Ok(())
+}
+
+#[test]
+fn parse_iter() -> Result<()> {
+    let source = "echo foo\n#$ foobar\necho bar";
+    let mut iter = ScriptParser::iter(source);
+    assert!(matches!(
+        iter.next(),
+        Some(Ok(Instruction::SendLine("echo foo")))
+    ));
+    assert!(matches!(
+        iter.next(),
+        Some(Err(Error::UnknownInstruction(_, _)))
+    ));
+    assert!(iter.next().is_none());
+
+    let source = "#!sh\necho foo\n#$ expect foo";
+    let instructions =
+        ScriptParser::iter(source).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(3, instructions.len());
+    Ok(())
+}
+
+#[test]
+fn parse_iter_without_filesystem() -> Result<()> {
+    let source = "#$ include missing/*.sh\n#$ include missing.sh\n\
+                  echo foo\n#$ expect foo";
+    let mut iter = ScriptParser::iter(source);
+    let instructions = iter.by_ref().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(2, instructions.len());
+    assert!(matches!(
+        instructions.first(),
+        Some(Instruction::SendLine("echo foo"))
+    ));
+    let includes = iter.includes();
+    assert_eq!(2, includes.len());
+    assert_eq!("missing/*.sh", includes[0].text);
+    assert_eq!(0, includes[0].index);
+    assert_eq!("missing.sh", includes[1].text);
+    assert_eq!(2, includes[1].location.line);
+
+    // Includes are only resolved when parsing
+    let result = ScriptParser::parse(source);
+    assert!(matches!(result, Err(Error::Include(..))));
+    Ok(())
+}
+
+#[test]
+fn parse_line_ending() -> Result<()> {
+    let source = "#$ lineending lf\n#$ lineending crlf\n#$ lineending cr";
+    let instructions = ScriptParser::parse(source)?;
+    assert_eq!(3, instructions.len());
+    let mut it = instructions.iter();
+    assert!(matches!(
+        it.next(),
+        Some(Instruction::LineEnding(LineEnding::Lf))
+    ));
+    assert!(matches!(
+        it.next(),
+        Some(Instruction::LineEnding(LineEnding::CrLf))
+    ));
+    assert!(matches!(
+        it.next(),
+        Some(Instruction::LineEnding(LineEnding::Cr))
+    ));
+
+    let result = ScriptParser::parse("#$ lineending lfcr");
+    assert!(matches!(result, Err(Error::InvalidLineEnding(_, _))));
+    Ok(())
+}
+
 #[test]
 fn parse_unknown() -> Result<()> {
-    let source = "#$ foobar";
+    let source = "echo foo\n#$ foobar";
     let result = ScriptParser::parse(source);
-    assert!(matches!(result, Err(Error::UnknownInstruction(_))));
+    assert!(matches!(result, Err(Error::UnknownInstruction(_, _))));
 
-    if let Err(Error::UnknownInstruction(cmd)) = result {
+    if let Err(Error::UnknownInstruction(cmd, location)) = result {
         assert_eq!("#$ foobar", cmd);
+        assert_eq!(2, location.line);
+        assert_eq!(1, location.column);
     } else {
         panic!("expected unknown instruction error");
     }
@@ -284,9 +1031,9 @@ fn parse_unknown() -> Result<()> {
 fn parse_unknown_empty() -> Result<()> {
     let source = "#$";
     let result = ScriptParser::parse(source);
-    assert!(matches!(result, Err(Error::UnknownInstruction(_))));
+    assert!(matches!(result, Err(Error::UnknownInstruction(_, _))));
 
-    if let Err(Error::UnknownInstruction(cmd)) = result {
+    if let Err(Error::UnknownInstruction(cmd, _)) = result {
         assert_eq!("", cmd);
     } else {
         panic!("expected unknown instruction error");
@@ -298,6 +1045,45 @@ fn parse_unknown_empty() -> Result<()> {
 fn parse_include_missing() -> Result<()> {
     let file = "tests/fixtures/bad-include.sh";
     let result = ScriptFile::parse(file);
-    assert!(matches!(result, Err(Error::Include(_, _))));
+    assert!(matches!(result, Err(Error::Include(_, _, _))));
+    Ok(())
+}
+
+#[test]
+fn parse_include_roots() -> Result<()> {
+    let file = "tests/fixtures/include-root.sh";
+    let result = ScriptFile::parse(file);
+    assert!(matches!(result, Err(Error::Include(_, _, _))));
+
+    let roots = vec![
+        PathBuf::from("tests/fixtures/includes"),
+        PathBuf::from("tests/fixtures/snippets"),
+    ];
+    let file = ScriptFile::parse_with(file, &roots, |_| {})?;
+    let instructions = file.instructions();
+    assert_eq!(1, instructions.len());
+    if let Some(Instruction::Include(source)) = instructions.first() {
+        assert!(matches!(
+            source.borrow_instructions().first(),
+            Some(Instruction::SendLine("echo snippet"))
+        ));
+    } else {
+        panic!("expected include instruction");
+    }
+
+    let file = "tests/fixtures/bad-include.sh";
+    let result = ScriptFile::parse_with(file, &roots, |_| {});
+    if let Err(Error::Include(_, tried, _)) = result {
+        assert_eq!(
+            vec![
+                PathBuf::from("tests/fixtures/includes/missing.sh"),
+                PathBuf::from("tests/fixtures/includes/includes/missing.sh"),
+                PathBuf::from("tests/fixtures/snippets/includes/missing.sh"),
+            ],
+            tried
+        );
+    } else {
+        panic!("expected include error");
+    }
     Ok(())
 }
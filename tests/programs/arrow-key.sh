@@ -0,0 +1,11 @@
+#!/bin/bash
+
+set -e
+
+echo "ready"
+read -rsn3 key
+if [[ "$key" == $'\e[A' ]]; then
+  echo "pressed up"
+else
+  echo "pressed other"
+fi
@@ -0,0 +1,7 @@
+#!/bin/bash
+
+set -e
+
+printf "Are you sure? [y/n] "
+read -r answer
+echo "confirmed $answer"
@@ -0,0 +1,10 @@
+#!/bin/bash
+
+set -e
+
+count=1
+while true; do
+    printf "[%d]> " "$count"
+    IFS= read -r line || break
+    count=$((count + 1))
+done
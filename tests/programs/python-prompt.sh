@@ -0,0 +1,13 @@
+#!/bin/bash
+
+set -e
+
+prompt=">>> "
+while true; do
+    printf "%s" "$prompt"
+    IFS= read -r line || break
+    case "$line" in
+        *:) prompt="... " ;;
+        *) prompt=">>> " ;;
+    esac
+done
@@ -0,0 +1,8 @@
+#!/bin/bash
+
+set -e
+
+echo "compiling"
+sleep 0.3
+echo "finished"
+read -r line
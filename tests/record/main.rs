@@ -0,0 +1,161 @@
+#![cfg(unix)]
+use anyhow::Result;
+use std::{
+    fs,
+    process::{Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+#[test]
+#[ignore = "requires asciinema"]
+fn record_interrupt() -> Result<()> {
+    let output = "target/record-interrupt";
+    let cast = format!("{}/cancel.cast", output);
+    let _ = fs::remove_file(&cast);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+        .args(["record", "--overwrite", output])
+        .arg("tests/fixtures/cancel.sh")
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    // Interrupt once the recording has started writing events
+    let start = Instant::now();
+    while fs::read_to_string(&cast)
+        .map(|cast| cast.lines().count() < 2)
+        .unwrap_or(true)
+    {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        sleep(Duration::from_millis(50));
+    }
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()?;
+    assert!(status.success());
+
+    let status = child.wait()?;
+    assert!(!status.success());
+
+    let cast = fs::read_to_string(&cast)?;
+    let mut lines = cast.lines();
+    let header = lines.next().unwrap();
+    assert!(header.starts_with('{') && header.ends_with('}'));
+    assert!(header.contains("\"version\": 2"));
+    for line in lines {
+        assert!(line.starts_with('[') && line.ends_with(']'));
+    }
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires asciinema"]
+fn record_section_markers() -> Result<()> {
+    let output = "target/record-section-markers";
+    let result = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+        .args(["record", "--overwrite", "--section-markers", output])
+        .arg("tests/fixtures/section.sh")
+        .output()?;
+    assert!(result.status.success());
+
+    let cast = fs::read_to_string(format!("{}/section.cast", output))?;
+    let markers: Vec<&str> = cast
+        .lines()
+        .filter(|line| line.contains(", \"m\", "))
+        .collect();
+    assert_eq!(1, markers.len());
+    assert!(markers[0].ends_with(", \"m\", \"Greeting\"]"));
+    Ok(())
+}
+
+#[test]
+fn record_missing_asciinema() -> Result<()> {
+    let path = "target/record-missing-asciinema/bin";
+    fs::create_dir_all(path)?;
+    let result = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+        .env("PATH", path)
+        .args(["record", "--overwrite", "target/record-missing-asciinema"])
+        .arg("tests/fixtures/echo.sh")
+        .output()?;
+    assert!(!result.status.success());
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(stdout.contains("program 'asciinema' not found in PATH"));
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires asciinema"]
+fn record_stdout() -> Result<()> {
+    let fifo = "target/record-stdout.fifo";
+    let _ = fs::remove_file(fifo);
+    assert!(Command::new("mkfifo").arg(fifo).status()?.success());
+
+    // Opening a fifo blocks until both ends are open
+    let reader = std::thread::spawn(move || fs::read_to_string(fifo));
+    let result = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+        .args(["record", "-", "tests/fixtures/echo.sh"])
+        .stdout(fs::File::create(fifo)?)
+        .stderr(Stdio::piped())
+        .output()?;
+    let cast = reader.join().unwrap()?;
+    fs::remove_file(fifo)?;
+    assert!(result.status.success());
+
+    let mut lines = cast.lines();
+    let header = lines.next().unwrap();
+    assert!(header.starts_with('{') && header.contains("\"version\": 2"));
+    assert!(lines.all(|line| line.starts_with('[') && line.ends_with(']')));
+    let stderr = String::from_utf8(result.stderr)?;
+    assert!(stderr.contains("echo.sh"));
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires asciinema"]
+fn record_skip_unchanged() -> Result<()> {
+    let output = "target/record-skip-unchanged";
+    let cast = format!("{}/echo.cast", output);
+    let record = |args: &[&str]| -> Result<String> {
+        let result = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+            .arg("record")
+            .args(args)
+            .args([output, "tests/fixtures/echo.sh"])
+            .output()?;
+        assert!(result.status.success());
+        Ok(String::from_utf8(result.stdout)?)
+    };
+
+    let _ = fs::remove_dir_all(output);
+    record(&["--skip-unchanged"])?;
+    assert!(fs::metadata(format!("{}.hash", cast))?.is_file());
+    let modified = fs::metadata(&cast)?.modified()?;
+
+    // Unchanged script is skipped even when overwriting
+    let stdout = record(&["--skip-unchanged", "--overwrite"])?;
+    assert!(stdout.contains("echo.sh unchanged"));
+    assert_eq!(modified, fs::metadata(&cast)?.modified()?);
+
+    // Without the flag the recording is replaced
+    let stdout = record(&["--overwrite"])?;
+    assert!(!stdout.contains("unchanged"));
+    assert_ne!(modified, fs::metadata(&cast)?.modified()?);
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires asciinema"]
+fn record_eval() -> Result<()> {
+    let output = "target/record-eval";
+    let result = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+        .args(["record", "--overwrite", output])
+        .arg("tests/fixtures/eval.sh")
+        .output()?;
+    assert!(result.status.success());
+
+    // Eval commands run before the cast starts
+    let cast = fs::read_to_string(format!("{}/eval.cast", output))?;
+    assert!(!cast.contains("export ANTICIPATE_EVAL"));
+    assert!(cast.contains("printenv ANTICIPATE_EVAL"));
+    let first = cast.lines().nth(1).unwrap();
+    assert!(first.starts_with("[0.000000, \"o\", \"\\u001b[2J"));
+    Ok(())
+}
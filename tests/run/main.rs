@@ -0,0 +1,18 @@
+use anyhow::Result;
+use std::{fs, process::Command};
+
+#[test]
+fn run_report_parallel_failure() -> Result<()> {
+    let report = "target/run-report-parallel.xml";
+    let _ = fs::remove_file(report);
+    let result = Command::new(env!("CARGO_BIN_EXE_anticipate"))
+        .args(["run", "--parallel", "--report", report])
+        .arg("tests/fixtures/bad-include.sh")
+        .output()?;
+    assert!(!result.status.success());
+
+    let junit = fs::read_to_string(report)?;
+    assert!(junit.contains("<testcase name=\"bad-include.sh\""));
+    assert!(junit.contains("<failure message=\"include file"));
+    Ok(())
+}